@@ -1,145 +1,252 @@
+use bbst_comparison::avl_tree_by_arena::AvlTreeByArena;
 use bbst_comparison::avl_tree_by_box::AvlTreeByBox;
 use bbst_comparison::avl_tree_with_parent::AvlTreeWithParent;
-use bbst_comparison::bench_utils::{generate_initial_values, generate_queries, Query};
+use bbst_comparison::bench_utils::{
+    generate_initial_values, generate_read_heavy_queries, generate_rope_queries, read_trace, Large,
+    Payload, Query, Workload,
+};
+use bbst_comparison::counted_btree::CountedBTree;
+use bbst_comparison::rb_tree_by_arena::RbTreeByArena;
 use bbst_comparison::rb_tree_insert_based_with_parent::RbTreeInsertBasedWithParent;
 use bbst_comparison::rb_tree_with_parent::RbTreeWithParent;
+use bbst_comparison::skip_list::SkipList;
 use bbst_comparison::splay_tree_double_rotation_with_parent::SplayTreeDoubleRotationWithParent;
 use bbst_comparison::splay_tree_with_parent::SplayTreeWithParent;
+use bbst_comparison::test_utils::Sequence;
+use bbst_comparison::treap_with_parent::TreapWithParent;
 use bbst_comparison::unbalanced_tree_with_parent::UnbalancedTreeWithParent;
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use bbst_comparison::weight_balanced_tree_with_parent::WeightBalancedTreeWithParent;
+use criterion::measurement::WallTime;
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkGroup, Criterion};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::fs::File;
+use std::io::BufReader;
 
-#[allow(clippy::too_many_lines)]
-fn bench_avl_tree_operations(c: &mut Criterion) {
-    let mut group = c.benchmark_group("bbst_comparison");
+/// How often the `_compacted` arena benchmarks move their nodes into in-order layout.
+const COMPACT_EVERY: usize = 20_000;
 
-    group.bench_function("avl_with_parent", |b| {
-        b.iter(|| {
-            let initial_values = generate_initial_values();
-            let queries = generate_queries();
-            let mut tree: AvlTreeWithParent = initial_values.into_iter().collect();
-            for &query in &queries {
-                match query {
-                    Query::Insert { index, value } => {
-                        tree.insert(black_box(index), black_box(value));
-                    }
-                    Query::Remove { index } => {
-                        tree.remove(black_box(index));
-                    }
-                }
+/// Builds a tree from `initial_values` and runs `queries` on it, calling `compact`, if any,
+/// every `COMPACT_EVERY` queries.
+fn run_workload<T, U: Sequence<T>>(
+    initial_values: Vec<T>,
+    queries: Vec<Query<T>>,
+    compact: Option<fn(&mut U)>,
+) -> U {
+    let mut tree: U = initial_values.into_iter().collect();
+    let mut pieces = Vec::new();
+    for (i, query) in queries.into_iter().enumerate() {
+        if let Some(compact) = compact.filter(|_| (i + 1) % COMPACT_EVERY == 0) {
+            compact(&mut tree);
+        }
+        match query {
+            Query::Insert { index, value } => {
+                tree.insert(black_box(index), black_box(value));
             }
-        });
-    });
-
-    group.bench_function("avl_by_box", |b| {
-        b.iter(|| {
-            let initial_values = generate_initial_values();
-            let queries = generate_queries();
-            let mut tree: AvlTreeByBox = initial_values.into_iter().collect();
-            for &query in &queries {
-                match query {
-                    Query::Insert { index, value } => {
-                        tree.insert(black_box(index), black_box(value));
-                    }
-                    Query::Remove { index } => {
-                        tree.remove(black_box(index));
-                    }
-                }
+            Query::Remove { index } => {
+                tree.remove(black_box(index));
             }
-        });
-    });
-
-    group.bench_function("rb_with_parent", |b| {
-        b.iter(|| {
-            let initial_values = generate_initial_values();
-            let queries = generate_queries();
-            let mut tree: RbTreeWithParent = initial_values.into_iter().collect();
-            for &query in &queries {
-                match query {
-                    Query::Insert { index, value } => {
-                        tree.insert(black_box(index), black_box(value));
-                    }
-                    Query::Remove { index } => {
-                        tree.remove(black_box(index));
-                    }
-                }
+            Query::Get { index } => {
+                black_box(tree.get(black_box(index)));
             }
-        });
+            Query::Set { index, value } => {
+                tree.set(black_box(index), black_box(value));
+            }
+            Query::Split { index } => {
+                pieces.push(tree.split_off(black_box(index)));
+            }
+            Query::Concat => tree.append(&mut pieces.pop().unwrap()),
+        }
+    }
+    tree
+}
+
+/// Times `run_workload`. Generating the workload and dropping the tree are not timed.
+fn bench_tree<T: Payload, U: Sequence<T>>(
+    group: &mut BenchmarkGroup<WallTime>,
+    name: &str,
+    generate: impl Fn() -> (Vec<T>, Vec<Query<T>>),
+    compact: Option<fn(&mut U)>,
+) {
+    group.bench_function(name, |b| {
+        b.iter_batched(
+            &generate,
+            |(initial_values, queries)| run_workload::<T, U>(initial_values, queries, compact),
+            BatchSize::LargeInput,
+        );
     });
+}
 
-    group.bench_function("rb_insert_based_with_parent", |b| {
-        b.iter(|| {
-            let initial_values = generate_initial_values();
-            let queries = generate_queries();
-            let mut tree: RbTreeInsertBasedWithParent = initial_values.into_iter().collect();
-            for &query in &queries {
-                match query {
-                    Query::Insert { index, value } => {
-                        tree.insert(black_box(index), black_box(value));
-                    }
-                    Query::Remove { index } => {
-                        tree.remove(black_box(index));
-                    }
-                }
-            }
-        });
+fn bench_all<T: Payload>(
+    group: &mut BenchmarkGroup<WallTime>,
+    generate: impl Fn() -> (Vec<T>, Vec<Query<T>>) + Copy,
+) {
+    bench_tree::<T, AvlTreeWithParent<_>>(group, "avl_with_parent", generate, None);
+    bench_tree::<T, AvlTreeByBox<_>>(group, "avl_by_box", generate, None);
+    bench_tree::<T, AvlTreeByArena<_>>(group, "avl_by_arena", generate, None);
+    bench_tree::<T, AvlTreeByArena<_>>(
+        group,
+        "avl_by_arena_compacted",
+        generate,
+        Some(AvlTreeByArena::compact),
+    );
+    bench_tree::<T, RbTreeByArena<_>>(group, "rb_by_arena", generate, None);
+    bench_tree::<T, RbTreeByArena<_>>(
+        group,
+        "rb_by_arena_compacted",
+        generate,
+        Some(RbTreeByArena::compact),
+    );
+    bench_tree::<T, CountedBTree<_>>(group, "counted_btree", generate, None);
+    bench_tree::<T, SkipList<_>>(group, "skip_list", generate, None);
+    bench_tree::<T, RbTreeWithParent<_>>(group, "rb_with_parent", generate, None);
+    bench_tree::<T, RbTreeInsertBasedWithParent<_>>(
+        group,
+        "rb_insert_based_with_parent",
+        generate,
+        None,
+    );
+    bench_tree::<T, SplayTreeWithParent<_>>(group, "splay_with_parent", generate, None);
+    bench_tree::<T, SplayTreeDoubleRotationWithParent<_>>(
+        group,
+        "splay_double_rotation_with_parent",
+        generate,
+        None,
+    );
+    bench_tree::<T, TreapWithParent<_>>(group, "treap_with_parent", generate, None);
+    bench_tree::<T, UnbalancedTreeWithParent<_>>(group, "unbalanced_with_parent", generate, None);
+    bench_tree::<T, WeightBalancedTreeWithParent<_>>(
+        group,
+        "weight_balanced_with_parent",
+        generate,
+        None,
+    );
+}
+
+/// The per-tree, per-workload matrix: one group per workload, one benchmark per tree.
+fn bench_workloads(c: &mut Criterion) {
+    for workload in Workload::ALL {
+        let mut group = c.benchmark_group(format!("bbst_comparison/{}", workload.name()));
+        bench_all(&mut group, || workload.generate::<i32>());
+        group.finish();
+    }
+}
+
+fn bench_large_payload(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bbst_comparison_large");
+    bench_all(&mut group, || Workload::Uniform.generate::<Large>());
+    group.finish();
+}
+
+fn bench_rope(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bbst_comparison_rope");
+    bench_all(&mut group, || {
+        (generate_initial_values(), generate_rope_queries::<i32>())
     });
+    group.finish();
+}
 
-    group.bench_function("splay_with_parent", |b| {
-        b.iter(|| {
-            let initial_values = generate_initial_values();
-            let queries = generate_queries();
-            let mut tree: SplayTreeWithParent = initial_values.into_iter().collect();
-            for &query in &queries {
-                match query {
-                    Query::Insert { index, value } => {
-                        tree.insert(black_box(index), black_box(value));
-                    }
-                    Query::Remove { index } => {
-                        tree.remove(black_box(index));
-                    }
-                }
-            }
-        });
+fn bench_read_heavy(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bbst_comparison_read_heavy");
+    bench_all(&mut group, || {
+        (
+            generate_initial_values(),
+            generate_read_heavy_queries::<i32>(false),
+        )
     });
+    group.finish();
 
-    group.bench_function("splay_double_rotation_with_parent", |b| {
-        b.iter(|| {
-            let initial_values = generate_initial_values();
-            let queries = generate_queries();
-            let mut tree: SplayTreeDoubleRotationWithParent = initial_values.into_iter().collect();
-            for &query in &queries {
-                match query {
-                    Query::Insert { index, value } => {
-                        tree.insert(black_box(index), black_box(value));
-                    }
-                    Query::Remove { index } => {
-                        tree.remove(black_box(index));
-                    }
-                }
-            }
-        });
+    let mut group = c.benchmark_group("bbst_comparison_read_heavy_hot");
+    bench_all(&mut group, || {
+        (
+            generate_initial_values(),
+            generate_read_heavy_queries::<i32>(true),
+        )
     });
+    group.finish();
+}
 
-    group.bench_function("unbalanced_with_parent", |b| {
-        b.iter(|| {
-            let initial_values = generate_initial_values();
-            let queries = generate_queries();
-            let mut tree: UnbalancedTreeWithParent = initial_values.into_iter().collect();
-            for &query in &queries {
-                match query {
-                    Query::Insert { index, value } => {
-                        tree.insert(black_box(index), black_box(value));
-                    }
-                    Query::Remove { index } => {
-                        tree.remove(black_box(index));
-                    }
-                }
-            }
+/// Builds a tree by running the uniform workload, so that its shape reflects the
+/// balancing scheme.
+fn build_by_workload<U: Sequence<i32>>() -> U {
+    let (initial_values, queries) = Workload::Uniform.generate();
+    run_workload(initial_values, queries, None)
+}
+
+/// Replays the trace at `$BBST_TRACE`, written by `bench_utils::write_trace`, against every
+/// tree. Does nothing if the variable is not set.
+fn bench_replay(c: &mut Criterion) {
+    let Some(path) = std::env::var_os("BBST_TRACE") else {
+        return;
+    };
+    let file = File::open(&path).unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+    let (initial_values, queries) =
+        read_trace(BufReader::new(file)).unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+    let mut group = c.benchmark_group("bbst_comparison_replay");
+    bench_all(&mut group, || (initial_values.clone(), queries.clone()));
+    group.finish();
+}
+
+/// Registers a full scan and 1000 scans of 100 elements from random positions.
+macro_rules! bench_scan {
+    ($group:expr, $tree:ty, $name:literal) => {{
+        let tree: $tree = build_by_workload();
+        let mut rng = StdRng::seed_from_u64(42);
+        let starts: Vec<usize> = (0..1000)
+            .map(|_| rng.random_range(0..=tree.len() - 100))
+            .collect();
+        $group.bench_function(concat!("full/", $name), |b| {
+            b.iter(|| tree.iter().map(|&x| i64::from(x)).sum::<i64>());
         });
-    });
+        $group.bench_function(concat!("range/", $name), |b| {
+            b.iter(|| {
+                starts
+                    .iter()
+                    .map(|&start| {
+                        let range = tree.iter_from(black_box(start)).take(100);
+                        range.map(|&x| i64::from(x)).sum::<i64>()
+                    })
+                    .sum::<i64>()
+            });
+        });
+    }};
+}
 
+fn bench_scan(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bbst_comparison_scan");
+    bench_scan!(group, AvlTreeWithParent<i32>, "avl_with_parent");
+    bench_scan!(group, RbTreeWithParent<i32>, "rb_with_parent");
+    bench_scan!(
+        group,
+        RbTreeInsertBasedWithParent<i32>,
+        "rb_insert_based_with_parent"
+    );
+    bench_scan!(group, SplayTreeWithParent<i32>, "splay_with_parent");
+    bench_scan!(
+        group,
+        SplayTreeDoubleRotationWithParent<i32>,
+        "splay_double_rotation_with_parent"
+    );
+    bench_scan!(group, TreapWithParent<i32>, "treap_with_parent");
+    bench_scan!(
+        group,
+        UnbalancedTreeWithParent<i32>,
+        "unbalanced_with_parent"
+    );
+    bench_scan!(
+        group,
+        WeightBalancedTreeWithParent<i32>,
+        "weight_balanced_with_parent"
+    );
     group.finish();
 }
 
-criterion_group!(benches, bench_avl_tree_operations);
+criterion_group!(
+    benches,
+    bench_workloads,
+    bench_large_payload,
+    bench_read_heavy,
+    bench_rope,
+    bench_replay,
+    bench_scan
+);
 criterion_main!(benches);
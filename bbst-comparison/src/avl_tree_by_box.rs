@@ -1,16 +1,17 @@
 #![allow(clippy::unnecessary_box_returns)]
-use std::cmp::Ordering;
+use crate::stats;
+use std::{cmp::Ordering, fmt::Debug, ops::Range};
 
-pub struct AvlTreeByBox {
-    root: Option<Box<Node>>,
+pub struct AvlTreeByBox<T> {
+    root: Option<Box<Node<T>>>,
 }
-impl Default for AvlTreeByBox {
+impl<T> Default for AvlTreeByBox<T> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl AvlTreeByBox {
+impl<T> AvlTreeByBox<T> {
     pub fn new() -> Self {
         Self { root: None }
     }
@@ -20,7 +21,8 @@ impl AvlTreeByBox {
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
-    pub fn insert(&mut self, index: usize, value: i32) {
+    pub fn insert(&mut self, index: usize, value: T) {
+        stats::allocation();
         let c = Box::new(Node {
             left: None,
             right: None,
@@ -31,26 +33,92 @@ impl AvlTreeByBox {
         let (l, r) = split2(self.root.take(), index);
         self.root = Some(merge3(l, c, r));
     }
-    pub fn remove(&mut self, index: usize) -> i32 {
+    pub fn remove(&mut self, index: usize) -> T {
         let (l, c, r) = split3(self.root.take().unwrap(), index);
         self.root = merge2(l, r);
         c.value
     }
+    pub fn get(&self, mut index: usize) -> Option<&T> {
+        let mut x = self.root.as_deref()?;
+        loop {
+            stats::visit();
+            let llen = x.left.as_ref().map_or(0, |x| x.len);
+            match index.cmp(&llen) {
+                Ordering::Less => x = x.left.as_deref()?,
+                Ordering::Equal => return Some(&x.value),
+                Ordering::Greater => {
+                    x = x.right.as_deref()?;
+                    index -= llen + 1;
+                }
+            }
+        }
+    }
+    pub fn get_mut(&mut self, mut index: usize) -> Option<&mut T> {
+        let mut x = self.root.as_deref_mut()?;
+        loop {
+            stats::visit();
+            let llen = x.left.as_ref().map_or(0, |x| x.len);
+            match index.cmp(&llen) {
+                Ordering::Less => x = x.left.as_deref_mut()?,
+                Ordering::Equal => return Some(&mut x.value),
+                Ordering::Greater => {
+                    x = x.right.as_deref_mut()?;
+                    index -= llen + 1;
+                }
+            }
+        }
+    }
+    /// Replaces the element at `index` and returns the old one. Panics if out of range.
+    pub fn set(&mut self, index: usize, value: T) -> T {
+        std::mem::replace(self.get_mut(index).expect("index out of range"), value)
+    }
+    /// Splits off the elements from `index` on. Panics if `index > len`.
+    pub fn split_off(&mut self, index: usize) -> Self {
+        assert!(index <= self.len(), "index out of range");
+        let (l, r) = split2(self.root.take(), index);
+        self.root = l;
+        Self { root: r }
+    }
+    /// Moves all elements of `other` to the end of `self`.
+    pub fn append(&mut self, other: &mut Self) {
+        self.root = merge2(self.root.take(), other.root.take());
+    }
+    /// Moves the elements out in order.
+    fn into_vec(self) -> Vec<T> {
+        fn into_vec_recurse<T>(x: Link<T>, out: &mut Vec<T>) {
+            let Some(x) = x else { return };
+            let Node {
+                left, right, value, ..
+            } = *x;
+            into_vec_recurse(left, out);
+            out.push(value);
+            into_vec_recurse(right, out);
+        }
+        let mut values = Vec::with_capacity(self.len());
+        into_vec_recurse(self.root, &mut values);
+        values
+    }
 }
 
-impl FromIterator<i32> for AvlTreeByBox {
-    fn from_iter<T: IntoIterator<Item = i32>>(iter: T) -> Self {
-        fn from_iter_recurse(values: &[i32]) -> Option<Box<Node>> {
-            let n = values.len();
+crate::range_ops::impl_range_ops!(AvlTreeByBox);
+
+impl<T> FromIterator<T> for AvlTreeByBox<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        fn from_iter_recurse<T>(
+            values: &mut impl Iterator<Item = T>,
+            n: usize,
+        ) -> Option<Box<Node<T>>> {
             if n == 0 {
                 return None;
             }
-            let left = from_iter_recurse(&values[..n / 2]);
-            let right = from_iter_recurse(&values[n / 2 + 1..]);
+            let left = from_iter_recurse(values, n / 2);
+            let value = values.next().unwrap();
+            let right = from_iter_recurse(values, n - n / 2 - 1);
+            stats::allocation();
             let mut c = Box::new(Node {
                 left,
                 right,
-                value: values[n / 2],
+                value,
                 ht: 0,
                 len: 1,
             });
@@ -58,20 +126,23 @@ impl FromIterator<i32> for AvlTreeByBox {
             Some(c)
         }
         let values: Vec<_> = iter.into_iter().collect();
-        let root = from_iter_recurse(&values);
+        let n = values.len();
+        let root = from_iter_recurse(&mut values.into_iter(), n);
         Self { root }
     }
 }
 
-pub struct Node {
+pub struct Node<T> {
     left: Option<Box<Self>>,
     right: Option<Box<Self>>,
-    value: i32,
+    value: T,
     len: usize,
     ht: u8,
 }
 
-impl Node {
+type Link<T> = Option<Box<Node<T>>>;
+
+impl<T> Node<T> {
     fn update(&mut self) {
         self.len = 1;
         self.ht = 1;
@@ -86,7 +157,7 @@ impl Node {
     }
 }
 
-fn split2(x: Option<Box<Node>>, index: usize) -> (Option<Box<Node>>, Option<Box<Node>>) {
+fn split2<T>(x: Link<T>, index: usize) -> (Link<T>, Link<T>) {
     if index == x.as_ref().map_or(0, |x| x.len) {
         return (x, None);
     }
@@ -94,7 +165,9 @@ fn split2(x: Option<Box<Node>>, index: usize) -> (Option<Box<Node>>, Option<Box<
     (l, merge2(Some(c), r))
 }
 
-fn split3(mut x: Box<Node>, index: usize) -> (Option<Box<Node>>, Box<Node>, Option<Box<Node>>) {
+fn split3<T>(mut x: Box<Node<T>>, index: usize) -> (Link<T>, Box<Node<T>>, Link<T>) {
+    let _recursion = stats::recurse();
+    stats::visit();
     let llen = x.left.as_ref().map_or(0, |x| x.len);
     let l = x.left.take();
     let r = x.right.take();
@@ -112,15 +185,17 @@ fn split3(mut x: Box<Node>, index: usize) -> (Option<Box<Node>>, Box<Node>, Opti
     }
 }
 
-fn merge2(l: Option<Box<Node>>, r: Option<Box<Node>>) -> Option<Box<Node>> {
+fn merge2<T>(l: Link<T>, r: Link<T>) -> Link<T> {
     let Some(r) = r else { return l };
     let (_, c, r) = split3(r, 0);
     Some(merge3(l, c, r))
 }
 
-fn merge3(l: Option<Box<Node>>, mut c: Box<Node>, r: Option<Box<Node>>) -> Box<Node> {
+fn merge3<T>(l: Link<T>, mut c: Box<Node<T>>, r: Link<T>) -> Box<Node<T>> {
+    let _recursion = stats::recurse();
     match ht(l.as_deref()).cmp(&ht(r.as_deref())) {
         Ordering::Less => {
+            stats::visit();
             let mut r = r.unwrap();
             r.left = Some(merge3(l, c, r.left.take()));
             balance(r)
@@ -132,6 +207,7 @@ fn merge3(l: Option<Box<Node>>, mut c: Box<Node>, r: Option<Box<Node>>) -> Box<N
             c
         }
         Ordering::Greater => {
+            stats::visit();
             let mut l = l.unwrap();
             l.right = Some(merge3(l.right.take(), c, r));
             balance(l)
@@ -139,7 +215,8 @@ fn merge3(l: Option<Box<Node>>, mut c: Box<Node>, r: Option<Box<Node>>) -> Box<N
     }
 }
 
-fn balance(mut x: Box<Node>) -> Box<Node> {
+fn balance<T>(mut x: Box<Node<T>>) -> Box<Node<T>> {
+    stats::rebalance_step();
     match ht(x.left.as_deref()) as i8 - ht(x.right.as_deref()) as i8 {
         -2 => {
             x.right = x.right.map(|r| {
@@ -167,11 +244,12 @@ fn balance(mut x: Box<Node>) -> Box<Node> {
     x
 }
 
-fn ht(x: Option<&Node>) -> u8 {
+fn ht<T>(x: Option<&Node<T>>) -> u8 {
     x.map_or(0, |x| x.ht)
 }
 
-fn rotate_left(mut x: Box<Node>) -> Box<Node> {
+fn rotate_left<T>(mut x: Box<Node<T>>) -> Box<Node<T>> {
+    stats::rotation();
     let mut y = x.right.take().unwrap();
     x.right = y.left.take();
     x.update();
@@ -180,7 +258,8 @@ fn rotate_left(mut x: Box<Node>) -> Box<Node> {
     y
 }
 
-fn rotate_right(mut x: Box<Node>) -> Box<Node> {
+fn rotate_right<T>(mut x: Box<Node<T>>) -> Box<Node<T>> {
+    stats::rotation();
     let mut y = x.left.take().unwrap();
     x.left = y.right.take();
     x.update();
@@ -190,8 +269,8 @@ fn rotate_right(mut x: Box<Node>) -> Box<Node> {
 }
 
 #[allow(dead_code)]
-fn pretty(x: Option<&Node>) -> String {
-    fn pretty_recurse(x: &Node, s: &mut String, overlines: &mut Vec<bool>, dir: u8) {
+fn pretty<T: Debug>(x: Option<&Node<T>>) -> String {
+    fn pretty_recurse<T: Debug>(x: &Node<T>, s: &mut String, overlines: &mut Vec<bool>, dir: u8) {
         use std::fmt::Write;
         if let Some(l) = x.left.as_ref() {
             overlines.push(dir == 2);
@@ -200,7 +279,7 @@ fn pretty(x: Option<&Node>) -> String {
         }
         writeln!(
                 s,
-                "{overlines}{corner}{branch} {padding} {value} {{ left: {left}, right: {right}, ht: {ht}, len: {len} }}",
+                "{overlines}{corner}{branch} {padding} {value:?} {{ left: {left}, right: {right}, ht: {ht}, len: {len} }}",
                 overlines = overlines
                     .iter()
                     .map(|&b| if b { "│" } else { " " })
@@ -219,8 +298,8 @@ fn pretty(x: Option<&Node>) -> String {
                 },
                 padding = "┄".repeat(4_usize.saturating_sub(overlines.len())),
                 value = x.value,
-                left = x.left.as_ref().map_or_else(||"*".to_owned(), |x| x.value.to_string()),
-                right = x.right.as_ref().map_or_else(||"*".to_owned(), |x| x.value.to_string()),
+                left = x.left.as_ref().map_or_else(||"*".to_owned(), |x| format!("{:?}", x.value)),
+                right = x.right.as_ref().map_or_else(||"*".to_owned(), |x| format!("{:?}", x.value)),
                 ht = x.ht,
                 len = x.len,
             )
@@ -241,19 +320,19 @@ fn pretty(x: Option<&Node>) -> String {
     }
 }
 
-impl crate::test_utils::TreeNode for Node {
+impl<T> crate::test_utils::TreeNode<T> for Node<T> {
     fn left(&self) -> Option<&Self> {
         self.left.as_deref()
     }
     fn right(&self) -> Option<&Self> {
         self.right.as_deref()
     }
-    fn value(&self) -> i32 {
-        self.value
+    fn value(&self) -> &T {
+        &self.value
     }
 }
 
-impl crate::test_utils::Validatable for Node {
+impl<T> crate::test_utils::Validatable<T> for Node<T> {
     const HAS_PARENT_POINTER: bool = false;
 
     fn validate_root(&self) -> bool {
@@ -266,40 +345,67 @@ impl crate::test_utils::Validatable for Node {
     }
 }
 
-impl crate::test_utils::Tree for AvlTreeByBox {
-    type Node = Node;
+impl<T> crate::test_utils::Tree<T> for AvlTreeByBox<T> {
+    type Node = Node<T>;
 
     fn root(&self) -> Option<&Self::Node> {
         self.root.as_deref()
     }
+}
+
+impl<T> crate::test_utils::Sequence<T> for AvlTreeByBox<T> {
     fn len(&self) -> usize {
         self.len()
     }
-    fn insert(&mut self, index: usize, value: i32) {
+    fn insert(&mut self, index: usize, value: T) {
         self.insert(index, value);
     }
-    fn remove(&mut self, index: usize) -> i32 {
+    fn remove(&mut self, index: usize) -> T {
         self.remove(index)
     }
+    fn get(&mut self, index: usize) -> Option<&T> {
+        AvlTreeByBox::get(self, index)
+    }
+    fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.get_mut(index)
+    }
+    fn set(&mut self, index: usize, value: T) -> T {
+        self.set(index, value)
+    }
+    fn split_off(&mut self, index: usize) -> Self {
+        self.split_off(index)
+    }
+    fn append(&mut self, other: &mut Self) {
+        self.append(other);
+    }
+    fn insert_slice(&mut self, index: usize, values: &[T])
+    where
+        T: Clone,
+    {
+        self.insert_slice(index, values);
+    }
+    fn remove_range(&mut self, range: Range<usize>) -> Vec<T> {
+        self.remove_range(range)
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::test_utils::{self, Query};
+    use crate::test_utils;
     use rand::{rngs::StdRng, Rng, SeedableRng};
 
     #[test]
     fn test_from_iter() {
         let mut rng = StdRng::seed_from_u64(42);
-        for tid in 1..=200 {
+        for tid in 1..=test_utils::CASES {
             eprintln!("==== Case #{tid}");
             let n = rng.random_range(0..=20);
             let value_lim = 20;
             let vec = std::iter::repeat_with(|| rng.random_range(0..value_lim))
                 .take(n)
                 .collect::<Vec<_>>();
-            let tree: AvlTreeByBox = vec.iter().copied().collect();
+            let tree: AvlTreeByBox<i32> = vec.iter().copied().collect();
             eprintln!("vec = {vec:?}");
             eprintln!("tree:\n{}", pretty(tree.root.as_deref()));
             test_utils::validate(&tree);
@@ -310,53 +416,26 @@ mod test {
 
     #[test]
     fn test_random() {
-        let mut rng = StdRng::seed_from_u64(42);
-        for tid in 1..=200 {
-            let q = 200;
-            let len_max = rng.random_range(5..=50);
-            let value_lim = 20;
-            let mut n = 0_usize;
-            let queries: Vec<_> = std::iter::repeat_with(|| {
-                if rng.random_ratio(n as u32, len_max) {
-                    let index = rng.random_range(0..n);
-                    n -= 1;
-                    Query::Remove { index }
-                } else {
-                    let index = rng.random_range(0..=n);
-                    let value = rng.random_range(0..value_lim);
-                    n += 1;
-                    Query::Insert { index, value }
-                }
-            })
-            .take(q)
-            .collect();
-            let mut tree = AvlTreeByBox::new();
-            let mut vec = vec![];
-            for (qid, &query) in (1..).zip(&queries) {
-                eprintln!("==== Case #{tid}.{qid}: {query:?}");
-                match query {
-                    Query::Insert { index, value } => {
-                        tree.insert(index, value);
-                        vec.insert(index, value);
-                    }
-                    Query::Remove { index } => {
-                        let result = tree.remove(index);
-                        let expected = vec.remove(index);
-                        assert_eq!(result, expected);
-                    }
-                }
-                eprintln!("vec = {vec:?}");
-                eprintln!("tree:\n{}", pretty(tree.root.as_deref()));
-                test_utils::validate(&tree);
-                eprintln!("tree validated!");
-                assert_eq!(test_utils::collect(&tree), vec);
-                eprintln!();
-            }
-        }
+        test_utils::check_model::<AvlTreeByBox<i32>>();
+    }
+
+    #[test]
+    fn test_remove_range_past_end() {
+        test_utils::check_remove_range_past_end::<AvlTreeByBox<i32>>();
+    }
+
+    #[test]
+    fn test_split_append() {
+        test_utils::check_split_append::<AvlTreeByBox<i32>>();
+    }
+
+    #[test]
+    fn test_insert_slice_remove_range() {
+        test_utils::check_insert_slice_remove_range::<AvlTreeByBox<i32>>();
     }
 
     #[test]
     fn analyze_tree_stats() {
-        test_utils::analyze_tree_stats::<AvlTreeByBox>();
+        test_utils::analyze_tree_stats::<AvlTreeByBox<i32>>();
     }
 }
@@ -1,20 +1,29 @@
+use crate::handle::{Handle, RawNode, Slots, NO_SLOT};
+use crate::stats;
 use std::{
     cmp::Ordering,
+    fmt::Debug,
+    mem,
+    ops::Range,
     ptr::{self, null_mut},
 };
 
-pub struct AvlTreeWithParent {
-    root: *mut Node,
+pub struct AvlTreeWithParent<T> {
+    root: *mut Node<T>,
+    slots: Slots<Node<T>>,
 }
-impl Default for AvlTreeWithParent {
+impl<T> Default for AvlTreeWithParent<T> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl AvlTreeWithParent {
+impl<T> AvlTreeWithParent<T> {
     pub fn new() -> Self {
-        Self { root: null_mut() }
+        Self {
+            root: null_mut(),
+            slots: Slots::new(),
+        }
     }
     pub fn len(&self) -> usize {
         unsafe { self.root.as_ref().map_or(0, |r| r.len) }
@@ -22,76 +31,198 @@ impl AvlTreeWithParent {
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
-    pub fn insert(&mut self, index: usize, value: i32) {
+    pub fn insert(&mut self, index: usize, value: T) {
+        self.insert_node(index, value);
+    }
+    /// Inserts like `insert` and returns a handle to the element.
+    pub fn insert_with_handle(&mut self, index: usize, value: T) -> Handle {
+        let c = self.insert_node(index, value);
+        unsafe { self.slots.issue(c) }
+    }
+    /// Inserts a node without a slot and returns it.
+    fn insert_node(&mut self, index: usize, value: T) -> *mut Node<T> {
         unsafe {
-            let c = Box::leak(Box::new(Node {
+            stats::allocation();
+            let c = Box::into_raw(Box::new(Node {
                 parent: null_mut(),
                 left: null_mut(),
                 right: null_mut(),
                 value,
+                slot: NO_SLOT,
                 len: 1,
                 ht: 1,
             }));
             let (l, r) = split2(self.root, index);
             self.root = merge3(l, c, r);
+            c
         }
     }
-    pub fn remove(&mut self, index: usize) -> i32 {
+    pub fn remove(&mut self, index: usize) -> T {
         unsafe {
-            let (l, c, r) = split3(&mut *self.root, index);
+            let (l, c, r) = split3(self.root, index);
+            self.slots.remove((*c).slot);
             let c = Box::from_raw(c);
             self.root = merge2(l, r);
             c.value
         }
     }
+    pub fn get(&self, index: usize) -> Option<&T> {
+        unsafe { Node::find(self.root, index).as_ref().map(|x| &x.value) }
+    }
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        unsafe { Node::find(self.root, index).as_mut().map(|x| &mut x.value) }
+    }
+    /// Replaces the element at `index` and returns the old one. Panics if out of range.
+    pub fn set(&mut self, index: usize, value: T) -> T {
+        mem::replace(self.get_mut(index).expect("index out of range"), value)
+    }
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.iter_from(0)
+    }
+    /// Iterates over the elements from `index` on. Panics if `index > len`.
+    pub fn iter_from(&self, index: usize) -> Iter<'_, T> {
+        unsafe { Node::iter_from(self.root, index) }
+    }
+    /// Returns the current index of the element behind `handle`, or `None` if it has been
+    /// removed or belongs to another tree.
+    pub fn index_of(&self, handle: Handle) -> Option<usize> {
+        unsafe { Node::index_of(self.root, &self.slots, handle) }
+    }
+    /// Removes the element behind `handle`, or returns `None` if it has been removed.
+    pub fn remove_by_handle(&mut self, handle: Handle) -> Option<T> {
+        let index = self.index_of(handle)?;
+        Some(self.remove(index))
+    }
+    /// Splits off the elements from `index` on. Panics if `index > len`.
+    pub fn split_off(&mut self, index: usize) -> Self {
+        Self {
+            root: unsafe { Node::split_off(&mut self.root, index) },
+            slots: self.slots.share(),
+        }
+    }
+    /// Moves all elements of `other` to the end of `self`.
+    ///
+    /// Handles into `other` stay valid if both trees share a slot table, which they do if they
+    /// were split off the same tree after it issued a handle. Otherwise they are invalidated,
+    /// which takes time linear in `other.len()`.
+    pub fn append(&mut self, other: &mut Self) {
+        unsafe {
+            Node::append(&mut self.root, &self.slots, &mut other.root, &other.slots);
+        }
+    }
+    /// Moves the elements out in order, freeing the nodes.
+    fn into_vec(mut self) -> Vec<T> {
+        unsafe { self.slots.drain(mem::replace(&mut self.root, null_mut())) }
+    }
 }
 
-impl FromIterator<i32> for AvlTreeWithParent {
-    fn from_iter<T: IntoIterator<Item = i32>>(iter: T) -> Self {
-        fn from_iter_recurse(values: &[i32]) -> *mut Node {
+crate::range_ops::impl_range_ops!(AvlTreeWithParent);
+
+impl<T> Drop for AvlTreeWithParent<T> {
+    fn drop(&mut self) {
+        unsafe { self.slots.free_tree(self.root) };
+    }
+}
+
+impl<T> FromIterator<T> for AvlTreeWithParent<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        fn from_iter_recurse<T>(values: &mut impl Iterator<Item = T>, n: usize) -> *mut Node<T> {
             unsafe {
-                let n = values.len();
                 if n == 0 {
                     return null_mut();
                 }
-                let l = from_iter_recurse(&values[..n / 2]);
-                let c = Box::leak(Box::new(Node {
+                let l = from_iter_recurse(values, n / 2);
+                stats::allocation();
+                let c = Box::into_raw(Box::new(Node {
                     parent: null_mut(),
                     left: null_mut(),
                     right: null_mut(),
-                    value: values[n / 2],
+                    value: values.next().unwrap(),
+                    slot: NO_SLOT,
                     ht: 0,
                     len: 0,
                 }));
-                let r = from_iter_recurse(&values[n / 2 + 1..]);
-                c.left = l;
-                c.right = r;
+                let r = from_iter_recurse(values, n - n / 2 - 1);
+                (*c).left = l;
+                (*c).right = r;
                 if let Some(l) = l.as_mut() {
                     l.parent = c;
                 }
                 if let Some(r) = r.as_mut() {
                     r.parent = c;
                 }
-                c.update();
+                (*c).update();
                 c
             }
         }
         let values: Vec<_> = iter.into_iter().collect();
-        let root = from_iter_recurse(&values);
-        Self { root }
+        let n = values.len();
+        let root = from_iter_recurse(&mut values.into_iter(), n);
+        Self {
+            root,
+            slots: Slots::new(),
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a AvlTreeWithParent<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
     }
 }
 
-pub struct Node {
+/// An in-order iterator that steps through the parent pointers.
+pub type Iter<'a, T> = crate::handle::Iter<'a, T, Node<T>>;
+
+pub struct Node<T> {
     parent: *mut Self,
     left: *mut Self,
     right: *mut Self,
-    value: i32,
+    value: T,
+    slot: u32,
     len: usize,
     ht: u8,
 }
 
-impl Node {
+impl<T> RawNode for Node<T> {
+    type Value = T;
+
+    fn children(&mut self) -> (&mut *mut Self, &mut *mut Self) {
+        (&mut self.left, &mut self.right)
+    }
+    fn parent_ptr(&self) -> *mut Self {
+        self.parent
+    }
+    fn left_ptr(&self) -> *mut Self {
+        self.left
+    }
+    fn right_ptr(&self) -> *mut Self {
+        self.right
+    }
+    fn len(&self) -> usize {
+        self.len
+    }
+    fn slot_mut(&mut self) -> &mut u32 {
+        &mut self.slot
+    }
+    fn value(&self) -> &T {
+        &self.value
+    }
+    fn into_value(self) -> T {
+        self.value
+    }
+    unsafe fn split2(x: *mut Self, index: usize) -> (*mut Self, *mut Self) {
+        split2(x, index)
+    }
+    unsafe fn merge2(l: *mut Self, r: *mut Self) -> *mut Self {
+        merge2(l, r)
+    }
+}
+
+impl<T> Node<T> {
     unsafe fn update(&mut self) {
         self.len = 1;
         self.ht = 1;
@@ -106,65 +237,73 @@ impl Node {
     }
 }
 
-unsafe fn split2(x: *mut Node, index: usize) -> (*mut Node, *mut Node) {
+unsafe fn split2<T>(x: *mut Node<T>, index: usize) -> (*mut Node<T>, *mut Node<T>) {
     if index == x.as_ref().map_or(0, |x| x.len) {
         return (x, null_mut());
     }
-    let (l, c, r) = split3(&mut *x, index);
+    let (l, c, r) = split3(x, index);
     (l, merge2(c, r))
 }
 
-unsafe fn split3(mut x: &mut Node, mut index: usize) -> (*mut Node, &mut Node, *mut Node) {
+unsafe fn split3<T>(
+    mut x: *mut Node<T>,
+    mut index: usize,
+) -> (*mut Node<T>, *mut Node<T>, *mut Node<T>) {
     loop {
-        let llen = x.left.as_ref().map_or(0, |x| x.len);
+        stats::visit();
+        let llen = (*x).left.as_ref().map_or(0, |x| x.len);
         match index.cmp(&llen) {
-            Ordering::Less => x = &mut *x.left,
+            Ordering::Less => x = (*x).left,
             Ordering::Equal => break,
             Ordering::Greater => {
-                x = &mut *x.right;
+                x = (*x).right;
                 index -= llen + 1;
             }
         }
     }
-    while let Some(p) = x.parent.as_mut() {
-        if let Some(pp) = p.parent.as_mut() {
+    while !(*x).parent.is_null() {
+        stats::visit();
+        let p = (*x).parent;
+        if let Some(pp) = (*p).parent.as_mut() {
             if ptr::eq(pp.left, p) {
                 pp.left = x;
             } else {
                 pp.right = x;
             }
         }
-        x.parent = p.parent;
-        if ptr::eq(p.left, x) {
-            x.right = merge3(x.right, p, p.right);
+        (*x).parent = (*p).parent;
+        if ptr::eq((*p).left, x) {
+            (*x).right = merge3((*x).right, p, (*p).right);
         } else {
-            assert!(ptr::eq(p.right, x));
-            x.left = merge3(p.left, p, x.left);
+            assert!(ptr::eq((*p).right, x));
+            (*x).left = merge3((*p).left, p, (*x).left);
         }
     }
-    let l = x.left;
-    let r = x.right;
-    x.left = null_mut();
-    x.right = null_mut();
-    x.parent = null_mut();
+    let l = (*x).left;
+    let r = (*x).right;
+    (*x).left = null_mut();
+    (*x).right = null_mut();
+    (*x).parent = null_mut();
     if let Some(l) = l.as_mut() {
         l.parent = null_mut();
     }
     if let Some(r) = r.as_mut() {
         r.parent = null_mut();
     }
-    x.update();
+    (*x).update();
     (l, x, r)
 }
 
-unsafe fn merge2(l: *mut Node, r: *mut Node) -> *mut Node {
-    let Some(r) = r.as_mut() else { return l };
+unsafe fn merge2<T>(l: *mut Node<T>, r: *mut Node<T>) -> *mut Node<T> {
+    if r.is_null() {
+        return l;
+    }
     let (_, c, r) = split3(r, 0);
     merge3(l, c, r)
 }
 
-unsafe fn merge3(mut l: *mut Node, mut c: &mut Node, mut r: *mut Node) -> &mut Node {
-    c.parent = null_mut();
+unsafe fn merge3<T>(mut l: *mut Node<T>, mut c: *mut Node<T>, mut r: *mut Node<T>) -> *mut Node<T> {
+    (*c).parent = null_mut();
     if let Some(l) = l.as_mut() {
         l.parent = null_mut();
     }
@@ -176,60 +315,65 @@ unsafe fn merge3(mut l: *mut Node, mut c: &mut Node, mut r: *mut Node) -> &mut N
         Ordering::Less => {
             let mut p = null_mut();
             while ht(l) < ht(r) {
+                stats::visit();
                 p = r;
                 r = (*r).left;
             }
-            c.parent = p;
+            (*c).parent = p;
             is_less = true;
         }
         Ordering::Equal => {}
         Ordering::Greater => {
             let mut p = null_mut();
             while ht(l) > ht(r) {
+                stats::visit();
                 p = l;
                 l = (*l).right;
             }
-            c.parent = p;
+            (*c).parent = p;
         }
     }
-    c.left = l;
-    c.right = r;
-    if let Some(l) = c.left.as_mut() {
+    (*c).left = l;
+    (*c).right = r;
+    if let Some(l) = l.as_mut() {
         l.parent = c;
     }
-    if let Some(r) = c.right.as_mut() {
+    if let Some(r) = r.as_mut() {
         r.parent = c;
     }
-    if let Some(p) = c.parent.as_mut() {
+    if let Some(p) = (*c).parent.as_mut() {
         if is_less {
             p.left = c;
         } else {
             p.right = c;
         }
     }
-    c.update();
-    while let Some(p) = c.parent.as_mut() {
-        c = balance(p);
+    (*c).update();
+    while !(*c).parent.is_null() {
+        c = balance((*c).parent);
     }
     c
 }
 
-unsafe fn ht(x: *const Node) -> u8 {
+unsafe fn ht<T>(x: *const Node<T>) -> u8 {
     x.as_ref().map_or(0, |x| x.ht)
 }
 
-unsafe fn balance(mut x: &mut Node) -> &mut Node {
-    match ht(x.left) as i8 - ht(x.right) as i8 {
+unsafe fn balance<T>(mut x: *mut Node<T>) -> *mut Node<T> {
+    stats::rebalance_step();
+    match ht((*x).left) as i8 - ht((*x).right) as i8 {
         -2 => {
-            if let Some(r) = x.right.as_mut().filter(|r| ht(r.left) > ht(r.right)) {
-                x.right = rotate_right(r);
+            let r = (*x).right;
+            if ht((*r).left) > ht((*r).right) {
+                (*x).right = rotate_right(r);
             }
             x = rotate_left(x);
         }
-        -1..=1 => x.update(),
+        -1..=1 => (*x).update(),
         2 => {
-            if let Some(l) = x.left.as_mut().filter(|l| ht(l.left) < ht(l.right)) {
-                x.left = rotate_left(l);
+            let l = (*x).left;
+            if ht((*l).left) < ht((*l).right) {
+                (*x).left = rotate_left(l);
             }
             x = rotate_right(x);
         }
@@ -238,51 +382,53 @@ unsafe fn balance(mut x: &mut Node) -> &mut Node {
     x
 }
 
-unsafe fn rotate_left(x: &mut Node) -> &mut Node {
-    let y = &mut *x.right;
-    y.parent = x.parent;
-    if let Some(p) = y.parent.as_mut() {
+unsafe fn rotate_left<T>(x: *mut Node<T>) -> *mut Node<T> {
+    stats::rotation();
+    let y = (*x).right;
+    (*y).parent = (*x).parent;
+    if let Some(p) = (*y).parent.as_mut() {
         if ptr::eq(p.left, x) {
             p.left = y;
         } else {
             p.right = y;
         }
     }
-    x.right = y.left;
-    if let Some(c) = x.right.as_mut() {
+    (*x).right = (*y).left;
+    if let Some(c) = (*x).right.as_mut() {
         c.parent = x;
     }
-    x.parent = y;
-    y.left = x;
-    x.update();
-    y.update();
+    (*x).parent = y;
+    (*y).left = x;
+    (*x).update();
+    (*y).update();
     y
 }
 
-unsafe fn rotate_right(x: &mut Node) -> &mut Node {
-    let y = &mut *x.left;
-    y.parent = x.parent;
-    if let Some(p) = y.parent.as_mut() {
+unsafe fn rotate_right<T>(x: *mut Node<T>) -> *mut Node<T> {
+    stats::rotation();
+    let y = (*x).left;
+    (*y).parent = (*x).parent;
+    if let Some(p) = (*y).parent.as_mut() {
         if ptr::eq(p.right, x) {
             p.right = y;
         } else {
             p.left = y;
         }
     }
-    x.left = y.right;
-    if let Some(c) = x.left.as_mut() {
+    (*x).left = (*y).right;
+    if let Some(c) = (*x).left.as_mut() {
         c.parent = x;
     }
-    x.parent = y;
-    y.right = x;
-    x.update();
-    y.update();
+    (*x).parent = y;
+    (*y).right = x;
+    (*x).update();
+    (*y).update();
     y
 }
 
 #[allow(dead_code)]
-fn pretty(x: *mut Node) -> String {
-    fn pretty_recurse(x: &Node, s: &mut String, overlines: &mut Vec<bool>) {
+fn pretty<T: Debug>(x: *mut Node<T>) -> String {
+    fn pretty_recurse<T: Debug>(x: &Node<T>, s: &mut String, overlines: &mut Vec<bool>) {
         unsafe {
             use std::fmt::Write;
             let dir = match x.parent.as_ref() {
@@ -297,7 +443,7 @@ fn pretty(x: *mut Node) -> String {
             }
             writeln!(
                 s,
-                "{overlines}{corner}{branch} {padding} {value} {{ parent: {parent}, left: {left}, right: {right}, ht: {ht} }}",
+                "{overlines}{corner}{branch} {padding} {value:?} {{ parent: {parent}, left: {left}, right: {right}, ht: {ht} }}",
                 overlines = overlines
                     .iter()
                     .map(|&b| if b { "│" } else { " " })
@@ -316,9 +462,9 @@ fn pretty(x: *mut Node) -> String {
                 },
                 padding = "┄".repeat(4_usize.saturating_sub(overlines.len())),
                 value = x.value,
-                parent = x.parent.as_ref().map_or_else(||"*".to_owned(), |x| x.value.to_string()),
-                left = x.left.as_ref().map_or_else(||"*".to_owned(), |x| x.value.to_string()),
-                right = x.right.as_ref().map_or_else(||"*".to_owned(), |x| x.value.to_string()),
+                parent = x.parent.as_ref().map_or_else(||"*".to_owned(), |x| format!("{:?}", x.value)),
+                left = x.left.as_ref().map_or_else(||"*".to_owned(), |x| format!("{:?}", x.value)),
+                right = x.right.as_ref().map_or_else(||"*".to_owned(), |x| format!("{:?}", x.value)),
                 ht = x.ht,
             )
             .unwrap();
@@ -341,19 +487,19 @@ fn pretty(x: *mut Node) -> String {
     }
 }
 
-impl crate::test_utils::TreeNode for Node {
+impl<T> crate::test_utils::TreeNode<T> for Node<T> {
     fn left(&self) -> Option<&Self> {
         unsafe { self.left.as_ref() }
     }
     fn right(&self) -> Option<&Self> {
         unsafe { self.right.as_ref() }
     }
-    fn value(&self) -> i32 {
-        self.value
+    fn value(&self) -> &T {
+        &self.value
     }
 }
 
-impl crate::test_utils::Validatable for Node {
+impl<T> crate::test_utils::Validatable<T> for Node<T> {
     const HAS_PARENT_POINTER: bool = true;
 
     fn validate_root(&self) -> bool {
@@ -370,40 +516,90 @@ impl crate::test_utils::Validatable for Node {
     }
 }
 
-impl crate::test_utils::Tree for AvlTreeWithParent {
-    type Node = Node;
+impl<T> crate::test_utils::Tree<T> for AvlTreeWithParent<T> {
+    type Node = Node<T>;
 
     fn root(&self) -> Option<&Self::Node> {
         unsafe { self.root.as_ref() }
     }
+}
+
+impl<T> crate::test_utils::Sequence<T> for AvlTreeWithParent<T> {
     fn len(&self) -> usize {
         self.len()
     }
-    fn insert(&mut self, index: usize, value: i32) {
+    fn insert(&mut self, index: usize, value: T) {
         self.insert(index, value);
     }
-    fn remove(&mut self, index: usize) -> i32 {
+    fn remove(&mut self, index: usize) -> T {
         self.remove(index)
     }
+    fn get(&mut self, index: usize) -> Option<&T> {
+        AvlTreeWithParent::get(self, index)
+    }
+    fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.get_mut(index)
+    }
+    fn set(&mut self, index: usize, value: T) -> T {
+        self.set(index, value)
+    }
+    fn split_off(&mut self, index: usize) -> Self {
+        self.split_off(index)
+    }
+    fn append(&mut self, other: &mut Self) {
+        self.append(other);
+    }
+    fn insert_slice(&mut self, index: usize, values: &[T])
+    where
+        T: Clone,
+    {
+        self.insert_slice(index, values);
+    }
+    fn remove_range(&mut self, range: Range<usize>) -> Vec<T> {
+        self.remove_range(range)
+    }
+}
+
+impl<T> crate::test_utils::WithParent<T> for AvlTreeWithParent<T> {
+    type Iter<'a>
+        = Iter<'a, T>
+    where
+        T: 'a;
+
+    fn iter(&self) -> Iter<'_, T> {
+        self.iter()
+    }
+    fn iter_from(&self, index: usize) -> Iter<'_, T> {
+        self.iter_from(index)
+    }
+    fn insert_with_handle(&mut self, index: usize, value: T) -> Handle {
+        self.insert_with_handle(index, value)
+    }
+    fn index_of(&mut self, handle: Handle) -> Option<usize> {
+        AvlTreeWithParent::index_of(self, handle)
+    }
+    fn remove_by_handle(&mut self, handle: Handle) -> Option<T> {
+        self.remove_by_handle(handle)
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::test_utils::{self, Query};
+    use crate::test_utils;
     use rand::{rngs::StdRng, Rng, SeedableRng};
 
     #[test]
     fn test_from_iter() {
         let mut rng = StdRng::seed_from_u64(42);
-        for tid in 1..=200 {
+        for tid in 1..=test_utils::CASES {
             eprintln!("==== Case #{tid}");
             let n = rng.random_range(0..=20);
             let value_lim = 20;
             let vec = std::iter::repeat_with(|| rng.random_range(0..value_lim))
                 .take(n)
                 .collect::<Vec<_>>();
-            let tree: AvlTreeWithParent = vec.iter().copied().collect();
+            let tree: AvlTreeWithParent<i32> = vec.iter().copied().collect();
             eprintln!("vec = {vec:?}");
             eprintln!("tree\n{}", pretty(tree.root));
             test_utils::validate(&tree);
@@ -414,53 +610,65 @@ mod test {
 
     #[test]
     fn test_random() {
+        test_utils::check_model::<AvlTreeWithParent<i32>>();
+    }
+
+    #[test]
+    fn test_remove_range_past_end() {
+        test_utils::check_remove_range_past_end::<AvlTreeWithParent<i32>>();
+    }
+
+    #[test]
+    fn test_drop() {
+        test_utils::check_drop::<AvlTreeWithParent<test_utils::Tracked>>();
+    }
+
+    #[test]
+    fn test_string() {
         let mut rng = StdRng::seed_from_u64(42);
-        for tid in 1..=200 {
-            let q = 200;
-            let len_max = rng.random_range(5..=50);
-            let value_lim = 20;
-            let mut n = 0_usize;
-            let queries: Vec<_> = std::iter::repeat_with(|| {
-                if rng.random_ratio(n as u32, len_max) {
-                    let index = rng.random_range(0..n);
-                    n -= 1;
-                    Query::Remove { index }
-                } else {
-                    let index = rng.random_range(0..=n);
-                    let value = rng.random_range(0..value_lim);
-                    n += 1;
-                    Query::Insert { index, value }
-                }
-            })
-            .take(q)
-            .collect();
-            let mut tree = AvlTreeWithParent::new();
-            let mut vec = vec![];
-            for (qid, &query) in (1..).zip(&queries) {
-                eprintln!("==== Case #{tid}.{qid}: {query:?}");
-                match query {
-                    Query::Insert { index, value } => {
-                        tree.insert(index, value);
-                        vec.insert(index, value);
-                    }
-                    Query::Remove { index } => {
-                        let result = tree.remove(index);
-                        let expected = vec.remove(index);
-                        assert_eq!(result, expected);
-                    }
-                }
-                eprintln!("vec = {vec:?}");
-                eprintln!("tree\n{}", pretty(tree.root));
-                test_utils::validate(&tree);
-                eprintln!("tree validated!");
-                assert_eq!(test_utils::collect(&tree), vec);
-                eprintln!();
+        let mut tree: AvlTreeWithParent<String> = (0..10).map(|i| i.to_string()).collect();
+        let mut vec: Vec<String> = (0..10).map(|i| i.to_string()).collect();
+        for value in 10..10 + test_utils::CASES {
+            if rng.random_ratio(1, 3) {
+                let index = rng.random_range(0..vec.len());
+                assert_eq!(tree.remove(index), vec.remove(index));
+            } else {
+                let index = rng.random_range(0..=vec.len());
+                tree.insert(index, value.to_string());
+                vec.insert(index, value.to_string());
             }
         }
+        test_utils::validate(&tree);
+        assert_eq!(test_utils::collect(&tree), vec);
+    }
+
+    #[test]
+    fn test_iter() {
+        test_utils::check_iter::<AvlTreeWithParent<i32>>();
+    }
+
+    #[test]
+    fn test_handle() {
+        test_utils::check_handles::<AvlTreeWithParent<i32>>();
+    }
+
+    #[test]
+    fn test_handle_across_splits() {
+        test_utils::check_handles_across_splits::<AvlTreeWithParent<i32>>();
+    }
+
+    #[test]
+    fn test_split_append() {
+        test_utils::check_split_append::<AvlTreeWithParent<i32>>();
+    }
+
+    #[test]
+    fn test_insert_slice_remove_range() {
+        test_utils::check_insert_slice_remove_range::<AvlTreeWithParent<i32>>();
     }
 
     #[test]
     fn analyze_tree_stats() {
-        test_utils::analyze_tree_stats::<AvlTreeWithParent>();
+        test_utils::analyze_tree_stats::<AvlTreeWithParent<i32>>();
     }
 }
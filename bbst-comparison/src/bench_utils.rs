@@ -1,16 +1,75 @@
+use std::io::{self, Read, Write};
+
 use rand::{rngs::StdRng, Rng, SeedableRng};
 
-#[derive(Debug, Clone, Copy)]
-pub enum Query {
-    Insert { index: usize, value: i32 },
-    Remove { index: usize },
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Query<T> {
+    Insert {
+        index: usize,
+        value: T,
+    },
+    Remove {
+        index: usize,
+    },
+    Get {
+        index: usize,
+    },
+    Set {
+        index: usize,
+        value: T,
+    },
+    /// Splits off the elements from `index` on and pushes them onto a stack of pieces.
+    Split {
+        index: usize,
+    },
+    /// Pops the last piece and appends it.
+    Concat,
+}
+
+/// An element type for the benchmarks.
+///
+/// Payloads are derived from a random `i32` key, so every element type sees the same
+/// sequence of indices.
+pub trait Payload {
+    fn from_key(key: i32) -> Self;
+}
+
+impl Payload for i32 {
+    fn from_key(key: i32) -> Self {
+        key
+    }
+}
+
+impl Payload for String {
+    fn from_key(key: i32) -> Self {
+        key.to_string()
+    }
+}
+
+/// A 64-byte payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Large {
+    pub key: i32,
+    pub padding: [u32; 15],
 }
 
-pub fn generate_queries() -> Vec<Query> {
+impl Payload for Large {
+    fn from_key(key: i32) -> Self {
+        Self {
+            key,
+            padding: [key as u32; 15],
+        }
+    }
+}
+
+/// Length of the benchmark workloads. Miri runs a reduced suite.
+const SIZE: usize = if cfg!(miri) { 200 } else { 200_000 };
+
+pub fn generate_queries<T: Payload>() -> Vec<Query<T>> {
     let mut rng = StdRng::seed_from_u64(42);
-    let n_initial = 200_000;
-    let len_max = 200_000;
-    let q = 200_000;
+    let n_initial = SIZE;
+    let len_max = SIZE as u32;
+    let q = SIZE;
     let value_lim = 1_000_000_000;
 
     let mut n = n_initial;
@@ -21,7 +80,7 @@ pub fn generate_queries() -> Vec<Query> {
             Query::Remove { index }
         } else {
             let index = rng.random_range(0..=n);
-            let value = rng.random_range(0..value_lim);
+            let value = T::from_key(rng.random_range(0..value_lim));
             n += 1;
             Query::Insert { index, value }
         }
@@ -30,11 +89,555 @@ pub fn generate_queries() -> Vec<Query> {
     .collect()
 }
 
-pub fn generate_initial_values() -> Vec<i32> {
+pub fn generate_initial_values<T: Payload>() -> Vec<T> {
     let mut rng = StdRng::seed_from_u64(42);
-    let n_initial = 200_000;
+    let n_initial = SIZE;
     let value_lim = 1_000_000_000;
     (0..n_initial)
-        .map(|_| rng.random_range(0..value_lim))
+        .map(|_| T::from_key(rng.random_range(0..value_lim)))
+        .collect()
+}
+
+/// 90% `Get`, 5% `Set` and 5% `Insert`/`Remove` on about 200k elements.
+///
+/// With `hot`, 90% of the accesses hit a window of 1% of the positions, which is where
+/// splay trees should pay off.
+pub fn generate_read_heavy_queries<T: Payload>(hot: bool) -> Vec<Query<T>> {
+    let mut rng = StdRng::seed_from_u64(42);
+    let n_initial = SIZE;
+    let q = SIZE;
+    let value_lim = 1_000_000_000;
+    let window = n_initial / 100;
+
+    let mut n = n_initial;
+    std::iter::repeat_with(|| {
+        let mut index = if hot && rng.random_ratio(9, 10) {
+            n / 2 - window / 2 + rng.random_range(0..window)
+        } else {
+            rng.random_range(0..n)
+        };
+        match rng.random_range(0..40) {
+            0 => {
+                n -= 1;
+                Query::Remove { index }
+            }
+            1 => {
+                index += usize::from(rng.random::<bool>());
+                let value = T::from_key(rng.random_range(0..value_lim));
+                n += 1;
+                Query::Insert { index, value }
+            }
+            2 | 3 => {
+                let value = T::from_key(rng.random_range(0..value_lim));
+                Query::Set { index, value }
+            }
+            _ => Query::Get { index },
+        }
+    })
+    .take(q)
+    .collect()
+}
+
+/// Insertions and removals interleaved with cutting the sequence with `Split` and joining
+/// the pieces back with `Concat`, with at most 8 pieces detached at a time.
+pub fn generate_rope_queries<T: Payload>() -> Vec<Query<T>> {
+    let mut rng = StdRng::seed_from_u64(42);
+    let n_initial = SIZE;
+    let len_max = SIZE as u32;
+    let q = SIZE;
+    let value_lim = 1_000_000_000;
+
+    let mut n = n_initial;
+    let mut total = n_initial;
+    let mut pieces = Vec::new();
+    std::iter::repeat_with(|| match rng.random_range(0..10) {
+        0 if pieces.len() < 8 => {
+            let index = rng.random_range(0..=n);
+            pieces.push(n - index);
+            n = index;
+            Query::Split { index }
+        }
+        1 if !pieces.is_empty() => {
+            n += pieces.pop().unwrap();
+            Query::Concat
+        }
+        // Keep the total length, pieces included, around `len_max`.
+        _ if n > 0 && rng.random_ratio((total as u32).min(len_max), len_max) => {
+            let index = rng.random_range(0..n);
+            n -= 1;
+            total -= 1;
+            Query::Remove { index }
+        }
+        _ => {
+            let index = rng.random_range(0..=n);
+            let value = T::from_key(rng.random_range(0..value_lim));
+            n += 1;
+            total += 1;
+            Query::Insert { index, value }
+        }
+    })
+    .take(q)
+    .collect()
+}
+
+/// The workloads of the per-tree, per-workload benchmark matrix, each of 200k queries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Workload {
+    /// Inserts at the back, starting from empty.
+    AppendOnly,
+    /// Inserts at the front, starting from empty.
+    PrependOnly,
+    /// `generate_queries`: insertions and removals at uniformly random positions.
+    Uniform,
+    /// Inserts near the back and removes near the front, like a queue.
+    SlidingWindow,
+    /// Insertions and removals at a cursor that moves by Zipf-distributed offsets.
+    ZipfLocal,
+    /// Inserts at random positions starting from empty, then removes until empty.
+    BuildThenDrain,
+}
+
+impl Workload {
+    pub const ALL: [Self; 6] = [
+        Self::AppendOnly,
+        Self::PrependOnly,
+        Self::Uniform,
+        Self::SlidingWindow,
+        Self::ZipfLocal,
+        Self::BuildThenDrain,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::AppendOnly => "append_only",
+            Self::PrependOnly => "prepend_only",
+            Self::Uniform => "uniform",
+            Self::SlidingWindow => "sliding_window",
+            Self::ZipfLocal => "zipf_local",
+            Self::BuildThenDrain => "build_then_drain",
+        }
+    }
+
+    /// Returns the initial values and the queries.
+    pub fn generate<T: Payload>(self) -> (Vec<T>, Vec<Query<T>>) {
+        match self {
+            Self::AppendOnly => (Vec::new(), generate_edge_queries(true)),
+            Self::PrependOnly => (Vec::new(), generate_edge_queries(false)),
+            Self::Uniform => (generate_initial_values(), generate_queries()),
+            Self::SlidingWindow => (generate_initial_values(), generate_sliding_window_queries()),
+            Self::ZipfLocal => (generate_initial_values(), generate_zipf_local_queries()),
+            Self::BuildThenDrain => (Vec::new(), generate_build_then_drain_queries()),
+        }
+    }
+}
+
+fn generate_edge_queries<T: Payload>(back: bool) -> Vec<Query<T>> {
+    let mut rng = StdRng::seed_from_u64(42);
+    let q = SIZE;
+    let value_lim = 1_000_000_000;
+
+    (0..q)
+        .map(|n| Query::Insert {
+            index: if back { n } else { 0 },
+            value: T::from_key(rng.random_range(0..value_lim)),
+        })
+        .collect()
+}
+
+fn generate_sliding_window_queries<T: Payload>() -> Vec<Query<T>> {
+    let mut rng = StdRng::seed_from_u64(42);
+    let n_initial = SIZE;
+    let q = SIZE;
+    let value_lim = 1_000_000_000;
+    let margin = 32;
+
+    let mut n = n_initial;
+    std::iter::repeat_with(|| {
+        if n > 0 && rng.random() {
+            let index = rng.random_range(0..n.min(margin));
+            n -= 1;
+            Query::Remove { index }
+        } else {
+            let index = n - rng.random_range(0..=n.min(margin));
+            let value = T::from_key(rng.random_range(0..value_lim));
+            n += 1;
+            Query::Insert { index, value }
+        }
+    })
+    .take(q)
+    .collect()
+}
+
+/// The cursor moves by a log-uniform offset, which approximates a Zipf distribution with
+/// exponent 1.
+fn generate_zipf_local_queries<T: Payload>() -> Vec<Query<T>> {
+    let mut rng = StdRng::seed_from_u64(42);
+    let n_initial = SIZE;
+    let len_max = SIZE as u32;
+    let q = SIZE;
+    let value_lim = 1_000_000_000;
+
+    let mut n = n_initial;
+    let mut cursor: usize = n / 2;
+    std::iter::repeat_with(|| {
+        let offset = ((n + 1) as f64).powf(rng.random::<f64>()) as usize - 1;
+        cursor = if rng.random() {
+            cursor.min(n).saturating_sub(offset)
+        } else {
+            (cursor + offset).min(n)
+        };
+        if rng.random_ratio(n as u32, len_max) {
+            let index = cursor.min(n - 1);
+            n -= 1;
+            Query::Remove { index }
+        } else {
+            let value = T::from_key(rng.random_range(0..value_lim));
+            n += 1;
+            Query::Insert {
+                index: cursor,
+                value,
+            }
+        }
+    })
+    .take(q)
+    .collect()
+}
+
+fn generate_build_then_drain_queries<T: Payload>() -> Vec<Query<T>> {
+    let mut rng = StdRng::seed_from_u64(42);
+    let q = SIZE;
+    let value_lim = 1_000_000_000;
+
+    let mut n = 0;
+    (0..q)
+        .map(|i| {
+            if i < q / 2 {
+                let index = rng.random_range(0..=n);
+                let value = T::from_key(rng.random_range(0..value_lim));
+                n += 1;
+                Query::Insert { index, value }
+            } else {
+                let index = rng.random_range(0..n);
+                n -= 1;
+                Query::Remove { index }
+            }
+        })
         .collect()
 }
+
+/// The on-disk format of a trace written by `write_trace`.
+///
+/// The text format starts with the line `bbst-trace v1`, followed by one line per initial
+/// value (`v <value>`) and then one line per query: `i <index> <value>`, `r <index>`,
+/// `g <index>`, `s <index> <value>`, `split <index>` or `concat`. Blank lines and lines
+/// starting with `#` are ignored.
+///
+/// The binary format starts with the magic `BBSTTRC1`, followed by the number of initial
+/// values as a `u64`, the values as `i32`s, the number of queries as a `u64` and the
+/// queries, each a tag byte (`i`, `r`, `g`, `s`, `x` for split, `c` for concat) followed by
+/// the index as a `u64` and the value as an `i32`, if any. Integers are little-endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    Text,
+    Binary,
+}
+
+const TEXT_HEADER: &str = "bbst-trace v1";
+const BINARY_MAGIC: &[u8; 8] = b"BBSTTRC1";
+
+pub fn write_trace(
+    mut writer: impl Write,
+    format: TraceFormat,
+    initial_values: &[i32],
+    queries: &[Query<i32>],
+) -> io::Result<()> {
+    match format {
+        TraceFormat::Text => {
+            writeln!(writer, "{TEXT_HEADER}")?;
+            for value in initial_values {
+                writeln!(writer, "v {value}")?;
+            }
+            for query in queries {
+                match *query {
+                    Query::Insert { index, value } => writeln!(writer, "i {index} {value}")?,
+                    Query::Remove { index } => writeln!(writer, "r {index}")?,
+                    Query::Get { index } => writeln!(writer, "g {index}")?,
+                    Query::Set { index, value } => writeln!(writer, "s {index} {value}")?,
+                    Query::Split { index } => writeln!(writer, "split {index}")?,
+                    Query::Concat => writeln!(writer, "concat")?,
+                }
+            }
+        }
+        TraceFormat::Binary => {
+            writer.write_all(BINARY_MAGIC)?;
+            writer.write_all(&(initial_values.len() as u64).to_le_bytes())?;
+            for value in initial_values {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+            writer.write_all(&(queries.len() as u64).to_le_bytes())?;
+            for query in queries {
+                let (tag, index, value) = match *query {
+                    Query::Insert { index, value } => (b'i', Some(index), Some(value)),
+                    Query::Remove { index } => (b'r', Some(index), None),
+                    Query::Get { index } => (b'g', Some(index), None),
+                    Query::Set { index, value } => (b's', Some(index), Some(value)),
+                    Query::Split { index } => (b'x', Some(index), None),
+                    Query::Concat => (b'c', None, None),
+                };
+                writer.write_all(&[tag])?;
+                if let Some(index) = index {
+                    writer.write_all(&(index as u64).to_le_bytes())?;
+                }
+                if let Some(value) = value {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+            }
+        }
+    }
+    writer.flush()
+}
+
+/// Reads a trace in either format and checks that every index is in range when the
+/// queries are replayed in order.
+pub fn read_trace(mut reader: impl Read) -> io::Result<(Vec<i32>, Vec<Query<i32>>)> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let (initial_values, queries) = if let Some(rest) = bytes.strip_prefix(BINARY_MAGIC) {
+        parse_binary_trace(rest)?
+    } else {
+        let text = std::str::from_utf8(&bytes).map_err(invalid_data)?;
+        parse_text_trace(text)?
+    };
+    check_trace(initial_values.len(), &queries)?;
+    Ok((initial_values, queries))
+}
+
+fn invalid_data(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+fn parse_text_trace(text: &str) -> io::Result<(Vec<i32>, Vec<Query<i32>>)> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+    if lines.next().map(|(_, line)| line) != Some(TEXT_HEADER) {
+        return Err(invalid_data(format!("missing `{TEXT_HEADER}` header")));
+    }
+
+    let mut initial_values = Vec::new();
+    let mut queries = Vec::new();
+    for (line_number, line) in lines {
+        let error = || invalid_data(format!("line {line_number}: malformed `{line}`"));
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let index = |i: usize| words.get(i).and_then(|w| w.parse().ok()).ok_or_else(error);
+        let value = |i: usize| words.get(i).and_then(|w| w.parse().ok()).ok_or_else(error);
+        let (arity, query) = match words[0] {
+            "v" if queries.is_empty() => {
+                if words.len() != 2 {
+                    return Err(error());
+                }
+                initial_values.push(value(1)?);
+                continue;
+            }
+            "i" => (
+                3,
+                Query::Insert {
+                    index: index(1)?,
+                    value: value(2)?,
+                },
+            ),
+            "r" => (2, Query::Remove { index: index(1)? }),
+            "g" => (2, Query::Get { index: index(1)? }),
+            "s" => (
+                3,
+                Query::Set {
+                    index: index(1)?,
+                    value: value(2)?,
+                },
+            ),
+            "split" => (2, Query::Split { index: index(1)? }),
+            "concat" => (1, Query::Concat),
+            _ => return Err(error()),
+        };
+        if words.len() != arity {
+            return Err(error());
+        }
+        queries.push(query);
+    }
+    Ok((initial_values, queries))
+}
+
+fn parse_binary_trace(mut bytes: &[u8]) -> io::Result<(Vec<i32>, Vec<Query<i32>>)> {
+    fn take<const N: usize>(bytes: &mut &[u8]) -> io::Result<[u8; N]> {
+        let (head, rest) = bytes
+            .split_first_chunk()
+            .ok_or_else(|| invalid_data("unexpected end of trace"))?;
+        *bytes = rest;
+        Ok(*head)
+    }
+    fn count(bytes: &mut &[u8]) -> io::Result<usize> {
+        usize::try_from(u64::from_le_bytes(take(bytes)?)).map_err(invalid_data)
+    }
+    let index = |bytes: &mut &[u8]| count(bytes);
+    let value = |bytes: &mut &[u8]| take(bytes).map(i32::from_le_bytes);
+
+    let n = count(&mut bytes)?;
+    let initial_values = (0..n)
+        .map(|_| value(&mut bytes))
+        .collect::<io::Result<_>>()?;
+    let q = count(&mut bytes)?;
+    let queries = (0..q)
+        .map(|_| {
+            Ok(match take::<1>(&mut bytes)?[0] {
+                b'i' => Query::Insert {
+                    index: index(&mut bytes)?,
+                    value: value(&mut bytes)?,
+                },
+                b'r' => Query::Remove {
+                    index: index(&mut bytes)?,
+                },
+                b'g' => Query::Get {
+                    index: index(&mut bytes)?,
+                },
+                b's' => Query::Set {
+                    index: index(&mut bytes)?,
+                    value: value(&mut bytes)?,
+                },
+                b'x' => Query::Split {
+                    index: index(&mut bytes)?,
+                },
+                b'c' => Query::Concat,
+                tag => return Err(invalid_data(format!("unknown query tag {tag:#04x}"))),
+            })
+        })
+        .collect::<io::Result<_>>()?;
+    if !bytes.is_empty() {
+        return Err(invalid_data("trailing bytes after the last query"));
+    }
+    Ok((initial_values, queries))
+}
+
+fn check_trace(n_initial: usize, queries: &[Query<i32>]) -> io::Result<()> {
+    let mut n = n_initial;
+    let mut pieces = Vec::new();
+    for (i, query) in queries.iter().enumerate() {
+        let in_range = match *query {
+            Query::Insert { index, .. } | Query::Split { index } => index <= n,
+            Query::Remove { index } | Query::Get { index } | Query::Set { index, .. } => index < n,
+            Query::Concat => !pieces.is_empty(),
+        };
+        if !in_range {
+            return Err(invalid_data(format!(
+                "query {i} ({query:?}) is out of range for length {n}"
+            )));
+        }
+        match *query {
+            Query::Insert { .. } => n += 1,
+            Query::Remove { .. } => n -= 1,
+            Query::Get { .. } | Query::Set { .. } => {}
+            Query::Split { index } => {
+                pieces.push(n - index);
+                n = index;
+            }
+            Query::Concat => n += pieces.pop().unwrap(),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Replays `queries` on a `Vec`, panicking if an index is out of range or a `Concat` has no
+    /// piece to append.
+    fn replay(name: &str, mut v: Vec<i32>, queries: Vec<Query<i32>>) {
+        let mut pieces = Vec::new();
+        for query in queries {
+            match query {
+                Query::Insert { index, value } => v.insert(index, value),
+                Query::Remove { index } => {
+                    v.remove(index);
+                }
+                Query::Get { index } | Query::Set { index, .. } => {
+                    assert!(index < v.len(), "{name}");
+                }
+                Query::Split { index } => pieces.push(v.split_off(index)),
+                Query::Concat => v.append(&mut pieces.pop().expect(name)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_workloads_stay_in_range() {
+        for workload in Workload::ALL {
+            let (v, queries) = workload.generate::<i32>();
+            replay(workload.name(), v, queries);
+        }
+        for hot in [false, true] {
+            let queries = generate_read_heavy_queries(hot);
+            replay("read_heavy", generate_initial_values(), queries);
+        }
+        replay("rope", generate_initial_values(), generate_rope_queries());
+    }
+
+    #[test]
+    fn test_trace_round_trip() {
+        let initial_values = generate_initial_values::<i32>();
+        let mut queries = generate_rope_queries::<i32>();
+        queries.truncate(2000);
+        queries.extend([
+            Query::Insert { index: 0, value: 3 },
+            Query::Insert { index: 0, value: 5 },
+            Query::Get { index: 0 },
+            Query::Set {
+                index: 1,
+                value: -7,
+            },
+        ]);
+        let initial_values = &initial_values[..];
+        for format in [TraceFormat::Text, TraceFormat::Binary] {
+            let mut bytes = Vec::new();
+            write_trace(&mut bytes, format, initial_values, &queries).unwrap();
+            let (v, q) = read_trace(&bytes[..]).unwrap();
+            assert_eq!(v, initial_values, "{format:?}");
+            assert_eq!(q, queries, "{format:?}");
+        }
+    }
+
+    #[test]
+    fn test_read_trace_errors() {
+        let read = |s: &str| read_trace(s.as_bytes()).map_err(|e| e.to_string());
+        assert_eq!(
+            read("bbst-trace v1\n# comment\n\nv 5\nv -3\ni 2 9\nr 0\n"),
+            Ok((
+                vec![5, -3],
+                vec![
+                    Query::Insert { index: 2, value: 9 },
+                    Query::Remove { index: 0 }
+                ]
+            )),
+        );
+        assert!(read("i 0 1\n").is_err());
+        assert!(read("bbst-trace v1\ni 0\n").is_err());
+        assert!(read("bbst-trace v1\ni 0 1 2\n").is_err());
+        assert!(read("bbst-trace v1\ni 1 1\n").is_err());
+        assert!(read("bbst-trace v1\nv 1\nr 0\nr 0\n").is_err());
+        assert!(read("bbst-trace v1\ni 0 1\nv 1\n").is_err());
+        assert!(read("bbst-trace v1\nconcat\n").is_err());
+
+        let mut bytes = Vec::new();
+        write_trace(
+            &mut bytes,
+            TraceFormat::Binary,
+            &[1],
+            &[Query::Remove { index: 0 }],
+        )
+        .unwrap();
+        assert!(read_trace(&bytes[..bytes.len() - 1]).is_err());
+        bytes.push(0);
+        assert!(read_trace(&bytes[..]).is_err());
+    }
+}
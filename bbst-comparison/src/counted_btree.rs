@@ -0,0 +1,599 @@
+use crate::stats;
+use crate::view::View;
+use std::{cell::OnceCell, cmp::Ordering, fmt::Debug, mem, ops::Range, ptr};
+
+/// A B+ tree whose internal nodes store the length of each child.
+///
+/// Elements live in the leaves only. Since the nodes do not fit `TreeNode`, `root` returns a
+/// binary view of the elements instead, which is built on first use and dropped on any
+/// mutation.
+///
+/// A node holds at most `B` entries (elements of a leaf, children of an internal node), and
+/// at least `B / 2` unless it is the root. The tests also run with a small `B` so that they
+/// reach several levels.
+pub struct CountedBTree<T, const B: usize = 32> {
+    root: Node<T, B>,
+    len: usize,
+    view: OnceCell<Option<Box<View<T>>>>,
+}
+impl<T, const B: usize> Default for CountedBTree<T, B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const B: usize> CountedBTree<T, B> {
+    pub fn new() -> Self {
+        Self {
+            root: Node::Leaf(Vec::new()),
+            len: 0,
+            view: OnceCell::new(),
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn insert(&mut self, index: usize, value: T) {
+        assert!(index <= self.len, "index out of range");
+        self.view.take();
+        if let Some(right) = insert_recurse(&mut self.root, index, value) {
+            let left = mem::replace(&mut self.root, Node::Leaf(Vec::new()));
+            self.root = grow(left, right);
+        }
+        self.len += 1;
+    }
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.len, "index out of range");
+        self.view.take();
+        let value = remove_recurse(&mut self.root, index);
+        if let Node::Internal { children, .. } = &mut self.root {
+            if children.len() == 1 {
+                self.root = children.pop().unwrap();
+            }
+        }
+        self.len -= 1;
+        value
+    }
+    pub fn get(&self, mut index: usize) -> Option<&T> {
+        if index >= self.len {
+            return None;
+        }
+        let mut x = &self.root;
+        loop {
+            stats::visit();
+            match x {
+                Node::Leaf(values) => return values.get(index),
+                Node::Internal { lens, children } => x = &children[locate(lens, &mut index)],
+            }
+        }
+    }
+    pub fn get_mut(&mut self, mut index: usize) -> Option<&mut T> {
+        if index >= self.len {
+            return None;
+        }
+        self.view.take();
+        let mut x = &mut self.root;
+        loop {
+            stats::visit();
+            match x {
+                Node::Leaf(values) => return values.get_mut(index),
+                Node::Internal { lens, children } => x = &mut children[locate(lens, &mut index)],
+            }
+        }
+    }
+    /// Replaces the element at `index` and returns the old one. Panics if out of range.
+    pub fn set(&mut self, index: usize, value: T) -> T {
+        mem::replace(self.get_mut(index).expect("index out of range"), value)
+    }
+    /// Splits off the elements from `index` on. Panics if `index > len`.
+    pub fn split_off(&mut self, index: usize) -> Self {
+        assert!(index <= self.len, "index out of range");
+        self.view.take();
+        let root = mem::replace(&mut self.root, Node::Leaf(Vec::new()));
+        let (l, r) = split(root, index);
+        self.root = l;
+        let len = mem::replace(&mut self.len, index) - index;
+        Self {
+            root: r,
+            len,
+            view: OnceCell::new(),
+        }
+    }
+    /// Moves all elements of `other` to the end of `self`.
+    pub fn append(&mut self, other: &mut Self) {
+        self.view.take();
+        other.view.take();
+        let l = mem::replace(&mut self.root, Node::Leaf(Vec::new()));
+        let r = mem::replace(&mut other.root, Node::Leaf(Vec::new()));
+        self.root = join(l, r);
+        self.len += mem::take(&mut other.len);
+    }
+    /// Moves the elements out in order.
+    fn into_vec(self) -> Vec<T> {
+        fn flatten<T, const B: usize>(x: Node<T, B>, out: &mut Vec<T>) {
+            match x {
+                Node::Leaf(mut values) => out.append(&mut values),
+                Node::Internal { children, .. } => {
+                    for child in children {
+                        flatten(child, out);
+                    }
+                }
+            }
+        }
+        let mut values = Vec::with_capacity(self.len);
+        flatten(self.root, &mut values);
+        values
+    }
+}
+
+crate::range_ops::impl_range_ops!(CountedBTree<T, B>, [T, const B: usize]);
+
+impl<T, const B: usize> FromIterator<T> for CountedBTree<T, B> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let values: Vec<_> = iter.into_iter().collect();
+        let len = values.len();
+        let mut level: Vec<_> = chunks(values, B).into_iter().map(Node::Leaf).collect();
+        while level.len() > 1 {
+            level = chunks(level, B)
+                .into_iter()
+                .map(|children| Node::Internal {
+                    lens: children.iter().map(Node::len).collect(),
+                    children,
+                })
+                .collect();
+        }
+        Self {
+            root: level.pop().unwrap(),
+            len,
+            view: OnceCell::new(),
+        }
+    }
+}
+
+/// Distributes `items` evenly over as few nodes of at most `cap` entries as possible.
+fn chunks<U>(items: Vec<U>, cap: usize) -> Vec<Vec<U>> {
+    let n = items.len();
+    let k = n.div_ceil(cap).max(1);
+    let mut items = items.into_iter();
+    (0..k)
+        .map(|j| items.by_ref().take(n * (j + 1) / k - n * j / k).collect())
+        .collect()
+}
+
+enum Node<T, const B: usize> {
+    Leaf(Vec<T>),
+    Internal {
+        lens: Vec<usize>,
+        children: Vec<Node<T, B>>,
+    },
+}
+
+impl<T, const B: usize> Node<T, B> {
+    fn count(&self) -> usize {
+        match self {
+            Node::Leaf(values) => values.len(),
+            Node::Internal { children, .. } => children.len(),
+        }
+    }
+    fn len(&self) -> usize {
+        match self {
+            Node::Leaf(values) => values.len(),
+            Node::Internal { lens, .. } => lens.iter().sum(),
+        }
+    }
+    fn height(&self) -> usize {
+        match self {
+            Node::Leaf(_) => 0,
+            Node::Internal { children, .. } => children[0].height() + 1,
+        }
+    }
+    fn is_empty(&self) -> bool {
+        self.count() == 0
+    }
+    /// Moves the second half of the entries to a new node.
+    fn split_half(&mut self) -> Self {
+        stats::rebalance_step();
+        stats::allocation();
+        match self {
+            Node::Leaf(values) => Node::Leaf(values.split_off(values.len() / 2)),
+            Node::Internal { lens, children } => {
+                let at = children.len() / 2;
+                Node::Internal {
+                    lens: lens.split_off(at),
+                    children: children.split_off(at),
+                }
+            }
+        }
+    }
+    /// Moves the entries of `other`, a node of the same height, to the end of `self`.
+    fn absorb(&mut self, other: Self) {
+        match (self, other) {
+            (Node::Leaf(a), Node::Leaf(mut b)) => a.append(&mut b),
+            (
+                Node::Internal { lens, children },
+                Node::Internal {
+                    lens: mut other_lens,
+                    children: mut other_children,
+                },
+            ) => {
+                lens.append(&mut other_lens);
+                children.append(&mut other_children);
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Returns the child containing the element at `index`, which becomes relative to it.
+fn locate(lens: &[usize], index: &mut usize) -> usize {
+    let mut i = 0;
+    while *index >= lens[i] {
+        *index -= lens[i];
+        i += 1;
+    }
+    i
+}
+
+/// Like `locate`, but for a gap before `index`, which may be at the end of a child.
+fn locate_gap(lens: &[usize], index: &mut usize) -> usize {
+    let mut i = 0;
+    while i + 1 < lens.len() && *index > lens[i] {
+        *index -= lens[i];
+        i += 1;
+    }
+    i
+}
+
+/// Returns the new right sibling of `x` if it overflows.
+fn insert_recurse<T, const B: usize>(
+    x: &mut Node<T, B>,
+    mut index: usize,
+    value: T,
+) -> Option<Node<T, B>> {
+    let _recursion = stats::recurse();
+    stats::visit();
+    match x {
+        Node::Leaf(values) => values.insert(index, value),
+        Node::Internal { lens, children } => {
+            let i = locate_gap(lens, &mut index);
+            if let Some(right) = insert_recurse(&mut children[i], index, value) {
+                lens[i] = children[i].len();
+                lens.insert(i + 1, right.len());
+                children.insert(i + 1, right);
+            } else {
+                lens[i] += 1;
+            }
+        }
+    }
+    (x.count() > B).then(|| x.split_half())
+}
+
+fn remove_recurse<T, const B: usize>(x: &mut Node<T, B>, mut index: usize) -> T {
+    let _recursion = stats::recurse();
+    stats::visit();
+    match x {
+        Node::Leaf(values) => values.remove(index),
+        Node::Internal { lens, children } => {
+            let i = locate(lens, &mut index);
+            let value = remove_recurse(&mut children[i], index);
+            lens[i] -= 1;
+            if children[i].count() < B / 2 {
+                fix_underflow(lens, children, i);
+            }
+            value
+        }
+    }
+}
+
+/// Merges the child at `i` with a neighbour, splitting them again if that overflows.
+fn fix_underflow<T, const B: usize>(
+    lens: &mut Vec<usize>,
+    children: &mut Vec<Node<T, B>>,
+    i: usize,
+) {
+    stats::rebalance_step();
+    let j = if i + 1 < children.len() { i } else { i - 1 };
+    let right = children.remove(j + 1);
+    lens.remove(j + 1);
+    children[j].absorb(right);
+    if children[j].count() > B {
+        let right = children[j].split_half();
+        lens.insert(j + 1, right.len());
+        children.insert(j + 1, right);
+    }
+    lens[j] = children[j].len();
+}
+
+/// Puts two roots of the same height under a new root.
+fn grow<T, const B: usize>(l: Node<T, B>, r: Node<T, B>) -> Node<T, B> {
+    stats::allocation();
+    let mut lens = vec![l.len(), r.len()];
+    let mut children = vec![l, r];
+    if children.iter().any(|c| c.count() < B / 2) {
+        fix_underflow(&mut lens, &mut children, 0);
+    }
+    if children.len() == 1 {
+        children.pop().unwrap()
+    } else {
+        Node::Internal { lens, children }
+    }
+}
+
+fn join<T, const B: usize>(mut l: Node<T, B>, mut r: Node<T, B>) -> Node<T, B> {
+    if l.is_empty() {
+        return r;
+    }
+    if r.is_empty() {
+        return l;
+    }
+    let (hl, hr) = (l.height(), r.height());
+    match hl.cmp(&hr) {
+        Ordering::Less => match join_left(&mut r, hr - hl, l) {
+            Some(s) => grow(r, s),
+            None => r,
+        },
+        Ordering::Equal => grow(l, r),
+        Ordering::Greater => match join_right(&mut l, hl - hr, r) {
+            Some(s) => grow(l, s),
+            None => l,
+        },
+    }
+}
+
+/// Attaches `r` as the last node `depth` levels down the right spine of `x`. Returns the new
+/// right sibling of `x` if it overflows.
+fn join_right<T, const B: usize>(
+    x: &mut Node<T, B>,
+    depth: usize,
+    r: Node<T, B>,
+) -> Option<Node<T, B>> {
+    let _recursion = stats::recurse();
+    stats::visit();
+    let Node::Internal { lens, children } = x else {
+        unreachable!()
+    };
+    let last = children.len() - 1;
+    if depth == 1 {
+        lens.push(r.len());
+        children.push(r);
+        if children[last + 1].count() < B / 2 {
+            fix_underflow(lens, children, last + 1);
+        }
+    } else {
+        if let Some(s) = join_right(&mut children[last], depth - 1, r) {
+            lens.push(s.len());
+            children.push(s);
+        }
+        lens[last] = children[last].len();
+    }
+    (x.count() > B).then(|| x.split_half())
+}
+
+/// Attaches `l` as the first node `depth` levels down the left spine of `x`. Returns the new
+/// right sibling of `x` if it overflows.
+fn join_left<T, const B: usize>(
+    x: &mut Node<T, B>,
+    depth: usize,
+    l: Node<T, B>,
+) -> Option<Node<T, B>> {
+    let _recursion = stats::recurse();
+    stats::visit();
+    let Node::Internal { lens, children } = x else {
+        unreachable!()
+    };
+    if depth == 1 {
+        lens.insert(0, l.len());
+        children.insert(0, l);
+        if children[0].count() < B / 2 {
+            fix_underflow(lens, children, 0);
+        }
+    } else {
+        if let Some(s) = join_left(&mut children[0], depth - 1, l) {
+            lens.insert(1, s.len());
+            children.insert(1, s);
+        }
+        lens[0] = children[0].len();
+    }
+    (x.count() > B).then(|| x.split_half())
+}
+
+/// Splits a root into two roots holding the first `index` elements and the rest.
+fn split<T, const B: usize>(x: Node<T, B>, mut index: usize) -> (Node<T, B>, Node<T, B>) {
+    let _recursion = stats::recurse();
+    stats::visit();
+    stats::allocation();
+    match x {
+        Node::Leaf(mut values) => {
+            let r = values.split_off(index);
+            (Node::Leaf(values), Node::Leaf(r))
+        }
+        Node::Internal {
+            mut lens,
+            mut children,
+        } => {
+            let i = locate_gap(&lens, &mut index);
+            let r = from_children(lens.split_off(i + 1), children.split_off(i + 1));
+            lens.pop();
+            let c = children.pop().unwrap();
+            let (cl, cr) = split(c, index);
+            (join(from_children(lens, children), cl), join(cr, r))
+        }
+    }
+}
+
+/// Makes a root out of a run of siblings.
+fn from_children<T, const B: usize>(lens: Vec<usize>, mut children: Vec<Node<T, B>>) -> Node<T, B> {
+    match children.len() {
+        0 => Node::Leaf(Vec::new()),
+        1 => children.pop().unwrap(),
+        _ => Node::Internal { lens, children },
+    }
+}
+
+/// Builds the view returned by `root`, a balanced binary tree over the elements.
+fn build_view<T, const B: usize>(root: &Node<T, B>) -> Option<Box<View<T>>> {
+    fn flatten<T, const B: usize>(
+        x: &Node<T, B>,
+        depth: usize,
+        height: usize,
+        mut valid: bool,
+        out: &mut Vec<(*const T, bool)>,
+    ) {
+        let count = x.count();
+        valid &= count <= B && (depth == 0 || count >= B / 2);
+        match x {
+            Node::Leaf(values) => {
+                valid &= depth == height;
+                out.extend(values.iter().map(|v| (ptr::from_ref(v), valid)));
+            }
+            Node::Internal { lens, children } => {
+                valid &= count >= 2;
+                for (&len, c) in lens.iter().zip(children) {
+                    flatten(c, depth + 1, height, valid && len == c.len(), out);
+                }
+            }
+        }
+    }
+    fn build_recurse<T>(
+        values: &mut impl Iterator<Item = (*const T, bool)>,
+        n: usize,
+    ) -> Option<Box<View<T>>> {
+        if n == 0 {
+            return None;
+        }
+        let left = build_recurse(values, n / 2);
+        let (value, valid) = values.next().unwrap();
+        let right = build_recurse(values, n - n / 2 - 1);
+        Some(Box::new(View::new(left, right, value, valid, None)))
+    }
+    let mut out = Vec::new();
+    flatten(root, 0, root.height(), true, &mut out);
+    let n = out.len();
+    build_recurse(&mut out.into_iter(), n)
+}
+
+#[allow(dead_code)]
+fn pretty<T: Debug, const B: usize>(x: &Node<T, B>) -> String {
+    fn pretty_recurse<T: Debug, const B: usize>(x: &Node<T, B>, s: &mut String, depth: usize) {
+        use std::fmt::Write;
+        match x {
+            Node::Leaf(values) => writeln!(s, "{}{values:?}", "  ".repeat(depth)).unwrap(),
+            Node::Internal { lens, children } => {
+                writeln!(s, "{}lens: {lens:?}", "  ".repeat(depth)).unwrap();
+                for c in children {
+                    pretty_recurse(c, s, depth + 1);
+                }
+            }
+        }
+    }
+    let mut s = String::new();
+    pretty_recurse(x, &mut s, 0);
+    assert_eq!(s.pop().unwrap(), '\n');
+    s
+}
+
+impl<T, const B: usize> crate::test_utils::Tree<T> for CountedBTree<T, B> {
+    type Node = View<T>;
+
+    fn root(&self) -> Option<&Self::Node> {
+        self.view.get_or_init(|| build_view(&self.root)).as_deref()
+    }
+}
+
+impl<T, const B: usize> crate::test_utils::Sequence<T> for CountedBTree<T, B> {
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn insert(&mut self, index: usize, value: T) {
+        self.insert(index, value);
+    }
+    fn remove(&mut self, index: usize) -> T {
+        self.remove(index)
+    }
+    fn get(&mut self, index: usize) -> Option<&T> {
+        CountedBTree::get(self, index)
+    }
+    fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.get_mut(index)
+    }
+    fn set(&mut self, index: usize, value: T) -> T {
+        self.set(index, value)
+    }
+    fn split_off(&mut self, index: usize) -> Self {
+        self.split_off(index)
+    }
+    fn append(&mut self, other: &mut Self) {
+        self.append(other);
+    }
+    fn insert_slice(&mut self, index: usize, values: &[T])
+    where
+        T: Clone,
+    {
+        self.insert_slice(index, values);
+    }
+    fn remove_range(&mut self, range: Range<usize>) -> Vec<T> {
+        self.remove_range(range)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    /// A fanout small enough for the tests to reach several levels.
+    type Small<T> = CountedBTree<T, 4>;
+
+    #[test]
+    fn test_from_iter() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for tid in 1..=test_utils::CASES {
+            eprintln!("==== Case #{tid}");
+            let n = rng.random_range(0..=20);
+            let value_lim = 20;
+            let vec = std::iter::repeat_with(|| rng.random_range(0..value_lim))
+                .take(n)
+                .collect::<Vec<_>>();
+            let tree: Small<i32> = vec.iter().copied().collect();
+            eprintln!("vec = {vec:?}");
+            eprintln!("tree:\n{}", pretty(&tree.root));
+            test_utils::validate(&tree);
+            eprintln!("tree validated!");
+            assert_eq!(test_utils::collect(&tree), vec);
+        }
+    }
+
+    #[test]
+    fn test_random() {
+        test_utils::check_model::<Small<i32>>();
+    }
+
+    #[test]
+    fn test_random_default_fanout() {
+        test_utils::check_model::<CountedBTree<i32>>();
+    }
+
+    #[test]
+    fn test_remove_range_past_end() {
+        test_utils::check_remove_range_past_end::<Small<i32>>();
+    }
+
+    #[test]
+    fn test_split_append() {
+        test_utils::check_split_append::<Small<i32>>();
+    }
+
+    #[test]
+    fn test_insert_slice_remove_range() {
+        test_utils::check_insert_slice_remove_range::<Small<i32>>();
+    }
+
+    #[test]
+    fn analyze_tree_stats() {
+        test_utils::analyze_sequence_stats::<Small<i32>>();
+    }
+}
@@ -0,0 +1,364 @@
+use crate::stats;
+use std::{
+    cell::RefCell,
+    cmp::Ordering,
+    iter::FusedIterator,
+    marker::PhantomData,
+    mem,
+    ptr::{self, null_mut},
+    rc::Rc,
+    sync::atomic::{self, AtomicU32},
+};
+
+/// A handle to an element of a parent-pointer tree, returned by `insert_with_handle`.
+///
+/// Handles are looked up in a slot table before any node is touched, so a handle whose
+/// element has been removed, or which was issued by an unrelated tree, is rejected. A tree
+/// creates its table when it issues its first handle, so plain `insert` and `remove` never
+/// touch it. Trees produced by `split_off` share the table of the original, if it has one
+/// yet, so handles follow their elements across `split_off` and `append`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle {
+    table: u32,
+    slot: u32,
+    generation: u32,
+}
+
+/// The slot of a node that no handle was issued for.
+pub(crate) const NO_SLOT: u32 = u32::MAX;
+
+/// Maps handles to nodes. A slot's generation is bumped when its node is removed.
+///
+/// The table is created by the first `insert`. Until then no node has a slot.
+pub(crate) struct Slots<N>(Option<Rc<RefCell<Table<N>>>>);
+
+struct Table<N> {
+    id: u32,
+    entries: Vec<(u32, *mut N)>,
+    free: Vec<u32>,
+}
+
+impl<N> Slots<N> {
+    pub(crate) fn new() -> Self {
+        Self(None)
+    }
+
+    /// Returns another reference to the same table, if there is one yet.
+    pub(crate) fn share(&self) -> Self {
+        Self(self.0.clone())
+    }
+
+    /// Returns whether both refer to the same table.
+    pub(crate) fn ptr_eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    /// Returns whether no handle issued by this table is still valid.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.as_ref().is_none_or(|table| {
+            let table = table.borrow();
+            table.entries.len() == table.free.len()
+        })
+    }
+
+    /// Registers `node` and returns its slot and a handle to it, creating the table first if
+    /// there is none.
+    pub(crate) fn insert(&mut self, node: *mut N) -> (u32, Handle) {
+        static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+        let table = self.0.get_or_insert_with(|| {
+            Rc::new(RefCell::new(Table {
+                id: NEXT_ID.fetch_add(1, atomic::Ordering::Relaxed),
+                entries: Vec::new(),
+                free: Vec::new(),
+            }))
+        });
+        let table = &mut *table.borrow_mut();
+        let slot = if let Some(slot) = table.free.pop() {
+            table.entries[slot as usize].1 = node;
+            slot
+        } else {
+            table.entries.push((0, node));
+            (table.entries.len() - 1) as u32
+        };
+        let handle = Handle {
+            table: table.id,
+            slot,
+            generation: table.entries[slot as usize].0,
+        };
+        (slot, handle)
+    }
+
+    /// Returns the node behind `handle`. It may belong to any tree sharing this table.
+    pub(crate) fn get(&self, handle: Handle) -> Option<*mut N> {
+        let table = self.0.as_ref()?.borrow();
+        if handle.table != table.id {
+            return None;
+        }
+        let &(generation, node) = table.entries.get(handle.slot as usize)?;
+        (generation == handle.generation).then_some(node)
+    }
+
+    /// Invalidates the handles to `slot`.
+    pub(crate) fn remove(&self, slot: u32) {
+        if slot == NO_SLOT {
+            return;
+        }
+        let table = self
+            .0
+            .as_ref()
+            .expect("a node has a slot but the tree has no table");
+        let table = &mut *table.borrow_mut();
+        let (generation, node) = &mut table.entries[slot as usize];
+        *generation = generation.wrapping_add(1);
+        *node = std::ptr::null_mut();
+        table.free.push(slot);
+    }
+}
+
+/// The links of a parent-pointer tree node, for the code shared between the trees.
+///
+/// The provided functions only walk the links and sizes, and leave balancing to `split2` and
+/// `merge2`. All of them take raw pointers to nodes of valid trees.
+pub(crate) trait RawNode: Sized {
+    type Value;
+
+    fn children(&mut self) -> (&mut *mut Self, &mut *mut Self);
+    fn left_ptr(&self) -> *mut Self;
+    fn right_ptr(&self) -> *mut Self;
+    fn parent_ptr(&self) -> *mut Self;
+    /// The number of nodes in the subtree.
+    fn len(&self) -> usize;
+    fn slot_mut(&mut self) -> &mut u32;
+    fn value(&self) -> &Self::Value;
+    fn into_value(self) -> Self::Value;
+    unsafe fn split2(x: *mut Self, index: usize) -> (*mut Self, *mut Self);
+    unsafe fn merge2(l: *mut Self, r: *mut Self) -> *mut Self;
+
+    /// Returns the node at `index`, or null if `index` is out of range.
+    unsafe fn find(mut x: *mut Self, mut index: usize) -> *mut Self {
+        while let Some(node) = x.as_ref() {
+            stats::visit();
+            let llen = node.left_ptr().as_ref().map_or(0, Self::len);
+            match index.cmp(&llen) {
+                Ordering::Less => x = node.left_ptr(),
+                Ordering::Equal => break,
+                Ordering::Greater => {
+                    x = node.right_ptr();
+                    index -= llen + 1;
+                }
+            }
+        }
+        x
+    }
+
+    /// Returns the in-order successor of `x`, or null.
+    unsafe fn next_node(mut x: *mut Self) -> *mut Self {
+        if !(*x).right_ptr().is_null() {
+            x = (*x).right_ptr();
+            while !(*x).left_ptr().is_null() {
+                x = (*x).left_ptr();
+            }
+            return x;
+        }
+        while let Some(p) = (*x).parent_ptr().as_ref() {
+            if ptr::eq(p.left_ptr(), x) {
+                return (*x).parent_ptr();
+            }
+            x = (*x).parent_ptr();
+        }
+        null_mut()
+    }
+
+    /// Returns the in-order predecessor of `x`, or null.
+    unsafe fn prev_node(mut x: *mut Self) -> *mut Self {
+        if !(*x).left_ptr().is_null() {
+            x = (*x).left_ptr();
+            while !(*x).right_ptr().is_null() {
+                x = (*x).right_ptr();
+            }
+            return x;
+        }
+        while let Some(p) = (*x).parent_ptr().as_ref() {
+            if ptr::eq(p.right_ptr(), x) {
+                return (*x).parent_ptr();
+            }
+            x = (*x).parent_ptr();
+        }
+        null_mut()
+    }
+
+    /// Invalidates the handles to the nodes under `x`.
+    unsafe fn release_slots(x: *mut Self, slots: &Slots<Self>) {
+        let mut x = Self::find(x, 0);
+        while let Some(node) = x.as_mut() {
+            slots.remove(mem::replace(node.slot_mut(), NO_SLOT));
+            x = Self::next_node(x);
+        }
+    }
+
+    /// Iterates over the elements of the tree under `root` from `index` on. Panics if
+    /// `index > len`.
+    unsafe fn iter_from<'a>(root: *mut Self, index: usize) -> Iter<'a, Self::Value, Self> {
+        let len = root.as_ref().map_or(0, Self::len);
+        assert!(index <= len, "index out of range");
+        let mut back = root;
+        while let Some(x) = back.as_ref().filter(|x| !x.right_ptr().is_null()) {
+            back = x.right_ptr();
+        }
+        Iter {
+            front: Self::find(root, index),
+            back,
+            len: len - index,
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the index of the node behind `handle` in the tree under `root` by climbing the
+    /// parent pointers, or `None` if it has been removed or is in another tree.
+    unsafe fn index_of(root: *mut Self, slots: &Slots<Self>, handle: Handle) -> Option<usize> {
+        let mut x = &*slots.get(handle)?;
+        let mut index = x.left_ptr().as_ref().map_or(0, Self::len);
+        while let Some(p) = x.parent_ptr().as_ref() {
+            if ptr::eq(p.right_ptr(), x) {
+                index += p.left_ptr().as_ref().map_or(0, Self::len) + 1;
+            }
+            x = p;
+        }
+        ptr::eq(x, root).then_some(index)
+    }
+
+    /// Splits off the elements of the tree under `root` from `index` on and returns the
+    /// root of the new tree. Panics if `index > len`.
+    unsafe fn split_off(root: &mut *mut Self, index: usize) -> *mut Self {
+        assert!(
+            index <= root.as_ref().map_or(0, Self::len),
+            "index out of range"
+        );
+        let (l, r) = Self::split2(*root, index);
+        *root = l;
+        r
+    }
+
+    /// Moves the tree under `other` to the end of the tree under `root`, invalidating the
+    /// handles into `other` unless both trees share their table.
+    unsafe fn append(
+        root: &mut *mut Self,
+        slots: &Slots<Self>,
+        other: &mut *mut Self,
+        other_slots: &Slots<Self>,
+    ) {
+        if !slots.ptr_eq(other_slots) && !other_slots.is_empty() {
+            Self::release_slots(*other, other_slots);
+        }
+        *root = Self::merge2(*root, mem::replace(other, null_mut()));
+    }
+}
+
+impl<N: RawNode> Slots<N> {
+    /// Registers `node` and returns a handle to it.
+    ///
+    /// # Safety
+    ///
+    /// `node` must be a valid node without a slot.
+    pub(crate) unsafe fn issue(&mut self, node: *mut N) -> Handle {
+        let (slot, handle) = self.insert(node);
+        *(*node).slot_mut() = slot;
+        handle
+    }
+
+    /// Frees the tree under `root` and invalidates the handles to its nodes.
+    ///
+    /// Detaches and frees leaves one at a time, climbing back up through the parent
+    /// pointers, so that deep trees need no stack.
+    ///
+    /// # Safety
+    ///
+    /// `root` must be null or the root of a tree of nodes allocated by `Box` whose slots
+    /// belong to this table. The tree must not be used afterwards.
+    pub(crate) unsafe fn free_tree(&self, root: *mut N) {
+        let mut x = root;
+        while let Some(node) = x.as_mut() {
+            let (left, right) = node.children();
+            if !left.is_null() {
+                x = std::mem::replace(left, std::ptr::null_mut());
+            } else if !right.is_null() {
+                x = std::mem::replace(right, std::ptr::null_mut());
+            } else {
+                x = node.parent_ptr();
+                self.remove(*node.slot_mut());
+                drop(Box::from_raw(node));
+            }
+        }
+    }
+
+    /// Frees the tree under `root` like `free_tree` and returns its values in order.
+    ///
+    /// # Safety
+    ///
+    /// The same as for `free_tree`.
+    pub(crate) unsafe fn drain(&self, root: *mut N) -> Vec<N::Value> {
+        let mut values = Vec::new();
+        let mut stack = Vec::new();
+        let mut x = root;
+        loop {
+            while let Some(node) = x.as_mut() {
+                stack.push(x);
+                x = *node.children().0;
+            }
+            let Some(node) = stack.pop() else { break };
+            let mut node = Box::from_raw(node);
+            self.remove(*node.slot_mut());
+            x = *node.children().1;
+            values.push(node.into_value());
+        }
+        values
+    }
+}
+
+/// An in-order iterator that steps through the parent pointers.
+pub struct Iter<'a, T, N> {
+    front: *mut N,
+    back: *mut N,
+    len: usize,
+    marker: PhantomData<&'a T>,
+}
+
+impl<'a, T, N: RawNode<Value = T> + 'a> Iterator for Iter<'a, T, N> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        unsafe {
+            let x = &*self.front;
+            self.front = N::next_node(self.front);
+            Some(x.value())
+        }
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T, N: RawNode<Value = T> + 'a> DoubleEndedIterator for Iter<'a, T, N> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        unsafe {
+            let x = &*self.back;
+            self.back = N::prev_node(self.back);
+            Some(x.value())
+        }
+    }
+}
+
+impl<'a, T, N: RawNode<Value = T> + 'a> ExactSizeIterator for Iter<'a, T, N> {}
+
+impl<'a, T, N: RawNode<Value = T> + 'a> FusedIterator for Iter<'a, T, N> {}
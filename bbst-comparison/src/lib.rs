@@ -1,9 +1,20 @@
+pub mod avl_tree_by_arena;
 pub mod avl_tree_by_box;
 pub mod avl_tree_with_parent;
 pub mod bench_utils;
+pub mod counted_btree;
+pub mod handle;
+mod range_ops;
+pub mod rb_tree_by_arena;
 pub mod rb_tree_insert_based_with_parent;
 pub mod rb_tree_with_parent;
+pub mod skip_list;
 pub mod splay_tree_double_rotation_with_parent;
 pub mod splay_tree_with_parent;
+pub mod stats;
 pub mod test_utils;
+pub mod treap_with_parent;
 pub mod unbalanced_tree_with_parent;
+pub mod view;
+pub mod weight_balanced_tree_with_parent;
+mod xorshift;
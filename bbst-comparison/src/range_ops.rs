@@ -0,0 +1,48 @@
+/// Implements `insert_slice` and `remove_range` on `$tree<T>` with `split_off`, `append` and
+/// `FromIterator`. A tree with more generic parameters is passed as its type followed by the
+/// parameters in brackets, as in `impl_range_ops!(Tree<T, N>, [T, const N: usize])`. The tree
+/// must have a private `fn into_vec(self) -> Vec<T>` that moves the elements out in order.
+///
+/// Every tree but the arena ones falls back to these two `append`s. In the boxed AVL tree and
+/// in the AVL, red-black, weight-balanced and unbalanced parent-pointer trees, `append` takes
+/// the first node of `other` out with `split3` and calls `merge3` around it, so the built
+/// subtree is joined with two `merge3`s after all, at the cost of two extra `split3`s. The
+/// splay trees splay the first node of `other` to its root and hang `self` off its left. The
+/// treap, the counted B-tree and the skip list have no `merge3`, and `append` is their join.
+macro_rules! impl_range_ops {
+    ($tree:ident) => {
+        crate::range_ops::impl_range_ops!($tree<T>, [T]);
+    };
+    ($ty:ty, [$($generics:tt)*]) => {
+        impl<$($generics)*> $ty {
+            /// Inserts `values` at `index`. Panics if `index > len`.
+            ///
+            /// The values are built in O(k) time like `from_iter` and joined in with two
+            /// `append`s, so this takes O(k) plus the time of a `split_off` and two `append`s.
+            pub fn insert_slice(&mut self, index: usize, values: &[T])
+            where
+                T: Clone,
+            {
+                // `merge3` joins two trees around a single node, not a whole tree, so joining
+                // the built tree in between the halves takes two joins either way. See the
+                // macro's doc comment for the trees this falls back to two `append`s in.
+                let mut right = self.split_off(index);
+                self.append(&mut values.iter().cloned().collect());
+                self.append(&mut right);
+            }
+
+            /// Removes the elements in `range` and returns them in order. Panics if the range
+            /// is out of bounds.
+            pub fn remove_range(&mut self, range: ::std::ops::Range<usize>) -> Vec<T> {
+                assert!(range.start <= range.end, "range start is after its end");
+                assert!(range.end <= self.len(), "index out of range");
+                let mut middle = self.split_off(range.start);
+                let mut right = middle.split_off(range.len());
+                self.append(&mut right);
+                middle.into_vec()
+            }
+        }
+    };
+}
+
+pub(crate) use impl_range_ops;
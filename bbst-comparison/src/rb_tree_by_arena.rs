@@ -0,0 +1,713 @@
+use crate::stats;
+use crate::view::View;
+use std::{
+    cell::OnceCell,
+    cmp::Ordering,
+    fmt::Debug,
+    mem::{self, MaybeUninit},
+    ops::{Index, IndexMut, Range},
+};
+use Color::{Black, Red};
+
+/// The index standing for no node.
+const NIL: u32 = u32::MAX;
+
+/// `RbTreeWithParent` with the nodes kept in a `Vec` and linked by `u32` indices.
+///
+/// Removed slots are reused through a free list. `compact` moves the nodes into in-order
+/// layout. `split_off` and `append` move the smaller part between arenas, in linear time.
+pub struct RbTreeByArena<T> {
+    nodes: Nodes<T>,
+    root: u32,
+    view: OnceCell<Option<Box<View<T>>>>,
+}
+impl<T> Default for RbTreeByArena<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> RbTreeByArena<T> {
+    pub fn new() -> Self {
+        Self {
+            nodes: Nodes::new(),
+            root: NIL,
+            view: OnceCell::new(),
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.nodes.len(self.root)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn insert(&mut self, index: usize, value: T) {
+        assert!(index <= self.len(), "index out of range");
+        self.view.take();
+        let c = self.nodes.alloc(value);
+        let (l, r) = self.nodes.split2(self.root, index);
+        self.root = self.nodes.merge3(l, c, r);
+    }
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.len(), "index out of range");
+        self.view.take();
+        let (l, c, r) = self.nodes.split3(self.root, index);
+        self.root = self.nodes.merge2(l, r);
+        self.nodes.dealloc(c)
+    }
+    pub fn get(&self, index: usize) -> Option<&T> {
+        let x = self.nodes.find(self.root, index);
+        (x != NIL).then(|| unsafe { self.nodes[x].value.assume_init_ref() })
+    }
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.view.take();
+        let x = self.nodes.find(self.root, index);
+        (x != NIL).then(|| unsafe { self.nodes[x].value.assume_init_mut() })
+    }
+    /// Replaces the element at `index` and returns the old one. Panics if out of range.
+    pub fn set(&mut self, index: usize, value: T) -> T {
+        mem::replace(self.get_mut(index).expect("index out of range"), value)
+    }
+    /// Splits off the elements from `index` on. Panics if `index > len`.
+    pub fn split_off(&mut self, index: usize) -> Self {
+        assert!(index <= self.len(), "index out of range");
+        self.view.take();
+        let (l, r) = self.nodes.split2(self.root, index);
+        let mut nodes = Nodes::new();
+        let root = if self.nodes.len(l) < self.nodes.len(r) {
+            self.root = self.nodes.transplant(l, &mut nodes);
+            mem::swap(&mut self.nodes, &mut nodes);
+            r
+        } else {
+            self.root = l;
+            self.nodes.transplant(r, &mut nodes)
+        };
+        Self {
+            nodes,
+            root,
+            view: OnceCell::new(),
+        }
+    }
+    /// Moves all elements of `other` to the end of `self`.
+    pub fn append(&mut self, other: &mut Self) {
+        self.view.take();
+        other.view.take();
+        let mut nodes = mem::replace(&mut other.nodes, Nodes::new());
+        let r = mem::replace(&mut other.root, NIL);
+        if nodes.len(r) > self.len() {
+            let l = self.nodes.transplant(self.root, &mut nodes);
+            self.nodes = nodes;
+            self.root = self.nodes.merge2(l, r);
+        } else {
+            let r = nodes.transplant(r, &mut self.nodes);
+            self.root = self.nodes.merge2(self.root, r);
+        }
+    }
+    /// Inserts `values` at `index`, which takes O(k + log n) time. Panics if `index > len`.
+    ///
+    /// All but the last value are built into a balanced subtree like `from_iter`. `merge3`
+    /// joins two trees around a single node, so the subtree is joined to the left half with
+    /// `merge2`, and that to the right half with `merge3` around the last value.
+    pub fn insert_slice(&mut self, index: usize, values: &[T])
+    where
+        T: Clone,
+    {
+        assert!(index <= self.len(), "index out of range");
+        let Some((last, init)) = values.split_last() else {
+            return;
+        };
+        self.view.take();
+        let (l, r) = self.nodes.split2(self.root, index);
+        let b = self.nodes.build(&mut init.iter().cloned(), init.len());
+        let c = self.nodes.alloc(last.clone());
+        let l = self.nodes.merge2(l, b);
+        self.root = self.nodes.merge3(l, c, r);
+    }
+    /// Removes the elements in `range` and returns them. Panics if the range is out of bounds.
+    pub fn remove_range(&mut self, range: Range<usize>) -> Vec<T> {
+        assert!(range.start <= range.end, "range start is after its end");
+        assert!(range.end <= self.len(), "index out of range");
+        self.view.take();
+        let (l, r) = self.nodes.split2(self.root, range.start);
+        let (c, r) = self.nodes.split2(r, range.len());
+        self.root = self.nodes.merge2(l, r);
+        self.nodes.drain(c)
+    }
+    /// Moves the nodes into a new arena in in-order layout, dropping the free slots.
+    pub fn compact(&mut self) {
+        self.view.take();
+        let mut nodes = Nodes::new();
+        self.root = self.nodes.transplant(self.root, &mut nodes);
+        self.nodes = nodes;
+    }
+}
+
+impl<T> FromIterator<T> for RbTreeByArena<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let values: Vec<_> = iter.into_iter().collect();
+        let n = values.len();
+        let mut nodes = Nodes::new();
+        let root = nodes.build(&mut values.into_iter(), n);
+        Self {
+            nodes,
+            root,
+            view: OnceCell::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Color {
+    Red,
+    Black,
+}
+
+struct Node<T> {
+    parent: u32,
+    left: u32,
+    right: u32,
+    len: u32,
+    bh: u8,
+    color: Color,
+    value: MaybeUninit<T>,
+}
+
+/// The arena. A slot is free iff it is in `free`, and then its value is uninitialized.
+struct Nodes<T> {
+    nodes: Vec<Node<T>>,
+    free: Vec<u32>,
+}
+
+impl<T> Index<u32> for Nodes<T> {
+    type Output = Node<T>;
+    fn index(&self, x: u32) -> &Node<T> {
+        &self.nodes[x as usize]
+    }
+}
+
+impl<T> IndexMut<u32> for Nodes<T> {
+    fn index_mut(&mut self, x: u32) -> &mut Node<T> {
+        &mut self.nodes[x as usize]
+    }
+}
+
+impl<T> Drop for Nodes<T> {
+    fn drop(&mut self) {
+        if !mem::needs_drop::<T>() {
+            return;
+        }
+        let mut is_free = vec![false; self.nodes.len()];
+        for &x in &self.free {
+            is_free[x as usize] = true;
+        }
+        for (node, is_free) in self.nodes.iter_mut().zip(is_free) {
+            if !is_free {
+                unsafe { node.value.assume_init_drop() };
+            }
+        }
+    }
+}
+
+impl<T> Nodes<T> {
+    fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    /// Builds a balanced subtree out of the first `n` of `values`.
+    fn build(&mut self, values: &mut impl Iterator<Item = T>, n: usize) -> u32 {
+        if n == 0 {
+            return NIL;
+        }
+        let l = self.build(values, n / 2);
+        let c = self.alloc(values.next().unwrap());
+        let r = self.build(values, n - n / 2 - 1);
+        if self.bh(l) > self.bh(r) {
+            self[l].color = Red;
+        }
+        self[c].color = Black;
+        self.attach(l, c, r);
+        c
+    }
+
+    fn alloc(&mut self, value: T) -> u32 {
+        let node = Node {
+            parent: NIL,
+            left: NIL,
+            right: NIL,
+            len: 1,
+            bh: 1,
+            color: Red,
+            value: MaybeUninit::new(value),
+        };
+        if let Some(x) = self.free.pop() {
+            self[x] = node;
+            x
+        } else {
+            stats::allocation();
+            self.nodes.push(node);
+            (self.nodes.len() - 1) as u32
+        }
+    }
+
+    /// Frees the subtree `x` and returns its elements in order.
+    fn drain(&mut self, mut x: u32) -> Vec<T> {
+        let mut values = Vec::with_capacity(self.len(x));
+        let mut stack = Vec::new();
+        loop {
+            while x != NIL {
+                stack.push(x);
+                x = self[x].left;
+            }
+            let Some(c) = stack.pop() else { break };
+            x = self[c].right;
+            values.push(self.dealloc(c));
+        }
+        values
+    }
+
+    fn dealloc(&mut self, x: u32) -> T {
+        self.free.push(x);
+        unsafe { self[x].value.assume_init_read() }
+    }
+
+    /// Moves the subtree `x` into `to` in in-order layout and returns its new root.
+    fn transplant(&mut self, x: u32, to: &mut Self) -> u32 {
+        if x == NIL {
+            return NIL;
+        }
+        let _recursion = stats::recurse();
+        let l = self.transplant(self[x].left, to);
+        let c = to.alloc(self.dealloc(x));
+        to[c].color = self[x].color;
+        let r = self.transplant(self[x].right, to);
+        to.attach(l, c, r);
+        c
+    }
+
+    fn len(&self, x: u32) -> usize {
+        if x == NIL {
+            0
+        } else {
+            self[x].len as usize
+        }
+    }
+
+    fn bh(&self, x: u32) -> u8 {
+        if x == NIL {
+            0
+        } else {
+            self[x].bh
+        }
+    }
+
+    fn color(&self, x: u32) -> Color {
+        if x == NIL {
+            Black
+        } else {
+            self[x].color
+        }
+    }
+
+    /// The black height of `x` as seen from its parent.
+    fn child_bh(&self, x: u32) -> u8 {
+        self.bh(x) + u8::from(self.color(x) == Black)
+    }
+
+    fn update(&mut self, x: u32) {
+        let (l, r) = (self[x].left, self[x].right);
+        self[x].len = (self.len(l) + self.len(r) + 1) as u32;
+        self[x].bh = self.child_bh(l).max(self.child_bh(r));
+    }
+
+    /// Makes `l` and `r` the children of `c`.
+    fn attach(&mut self, l: u32, c: u32, r: u32) {
+        self[c].left = l;
+        self[c].right = r;
+        if l != NIL {
+            self[l].parent = c;
+        }
+        if r != NIL {
+            self[r].parent = c;
+        }
+        self.update(c);
+    }
+
+    /// Makes `y` the child of `p` in place of `x`.
+    fn replace_child(&mut self, p: u32, x: u32, y: u32) {
+        if p == NIL {
+            return;
+        }
+        if self[p].left == x {
+            self[p].left = y;
+        } else {
+            self[p].right = y;
+        }
+    }
+
+    /// Returns the node at `index`, or `NIL` if `index` is out of range.
+    fn find(&self, mut x: u32, mut index: usize) -> u32 {
+        while x != NIL {
+            stats::visit();
+            let llen = self.len(self[x].left);
+            match index.cmp(&llen) {
+                Ordering::Less => x = self[x].left,
+                Ordering::Equal => break,
+                Ordering::Greater => {
+                    x = self[x].right;
+                    index -= llen + 1;
+                }
+            }
+        }
+        x
+    }
+
+    fn split2(&mut self, x: u32, index: usize) -> (u32, u32) {
+        if index == self.len(x) {
+            return (x, NIL);
+        }
+        let (l, c, r) = self.split3(x, index);
+        (l, self.merge3(NIL, c, r))
+    }
+
+    fn split3(&mut self, root: u32, index: usize) -> (u32, u32, u32) {
+        let x = self.find(root, index);
+        loop {
+            let p = self[x].parent;
+            if p == NIL {
+                break;
+            }
+            stats::visit();
+            let pp = self[p].parent;
+            self.replace_child(pp, p, x);
+            self[x].parent = pp;
+            if self[p].left == x {
+                let (l, r) = (self[x].right, self[p].right);
+                self[x].right = self.merge3(l, p, r);
+            } else {
+                let (l, r) = (self[p].left, self[x].left);
+                self[x].left = self.merge3(l, p, r);
+            }
+        }
+        let (l, r) = (self[x].left, self[x].right);
+        self[x].left = NIL;
+        self[x].right = NIL;
+        if l != NIL {
+            self[l].color = Black;
+            self[l].parent = NIL;
+        }
+        if r != NIL {
+            self[r].color = Black;
+            self[r].parent = NIL;
+        }
+        self.update(x);
+        (l, x, r)
+    }
+
+    fn merge2(&mut self, l: u32, r: u32) -> u32 {
+        if r == NIL {
+            return l;
+        }
+        let (_, c, r) = self.split3(r, 0);
+        self.merge3(l, c, r)
+    }
+
+    fn merge3(&mut self, mut l: u32, c: u32, mut r: u32) -> u32 {
+        self[c].color = Red;
+        self[c].parent = NIL;
+        if l != NIL {
+            self[l].color = Black;
+            self[l].parent = NIL;
+        }
+        if r != NIL {
+            self[r].color = Black;
+            self[r].parent = NIL;
+        }
+        let mut p = NIL;
+        match self.bh(l).cmp(&self.bh(r)) {
+            Ordering::Less => {
+                while self.bh(l) < self.bh(r) {
+                    stats::visit();
+                    p = r;
+                    r = self[r].left;
+                }
+                self[p].left = c;
+            }
+            Ordering::Equal => {}
+            Ordering::Greater => {
+                while self.bh(l) > self.bh(r) {
+                    stats::visit();
+                    p = l;
+                    l = self[l].right;
+                }
+                self[p].right = c;
+            }
+        }
+        self[c].parent = p;
+        self.attach(l, c, r);
+        let mut c = c;
+        while self[c].color == Red && self.color(self[c].parent) == Red {
+            stats::rebalance_step();
+            let p = self[c].parent;
+            let pp = self[p].parent;
+            let (ppl, ppr) = (self[pp].left, self[pp].right);
+            match (self.color(ppl), self.color(ppr)) {
+                (Red, Black) => {
+                    if self[p].right == c {
+                        self.rotate_left(p);
+                    }
+                    c = self.rotate_right(pp);
+                    break;
+                }
+                (Black, Red) => {
+                    if self[p].left == c {
+                        self.rotate_right(p);
+                    }
+                    c = self.rotate_left(pp);
+                    break;
+                }
+                _ => {}
+            }
+            self[ppl].color = Black;
+            self[pp].color = Red;
+            self[ppr].color = Black;
+            self.update(p);
+            self.update(pp);
+            c = pp;
+        }
+        while self[c].parent != NIL {
+            c = self[c].parent;
+            self.update(c);
+        }
+        self[c].color = Black;
+        c
+    }
+
+    /// Returns `x`, which has moved down.
+    fn rotate_left(&mut self, x: u32) -> u32 {
+        stats::rotation();
+        let y = self[x].right;
+        assert_eq!(self[y].color, Red);
+        self[y].color = self[x].color;
+        self[x].color = Red;
+        let p = self[x].parent;
+        self[y].parent = p;
+        self.replace_child(p, x, y);
+        let c = self[y].left;
+        self[x].right = c;
+        if c != NIL {
+            self[c].parent = x;
+        }
+        self[x].parent = y;
+        self[y].left = x;
+        self.update(x);
+        self.update(y);
+        x
+    }
+
+    /// Returns `x`, which has moved down.
+    fn rotate_right(&mut self, x: u32) -> u32 {
+        stats::rotation();
+        let y = self[x].left;
+        assert_eq!(self[y].color, Red);
+        self[y].color = self[x].color;
+        self[x].color = Red;
+        let p = self[x].parent;
+        self[y].parent = p;
+        self.replace_child(p, x, y);
+        let c = self[y].right;
+        self[x].left = c;
+        if c != NIL {
+            self[c].parent = x;
+        }
+        self[x].parent = y;
+        self[y].right = x;
+        self.update(x);
+        self.update(y);
+        x
+    }
+
+    /// Builds the view returned by `root`, with the same shape as the subtree `x`.
+    fn build_view(&self, x: u32, parent: u32) -> Option<Box<View<T>>> {
+        if x == NIL {
+            return None;
+        }
+        let node = &self[x];
+        let (l, r) = (node.left, node.right);
+        let valid = node.parent == parent
+            && (parent != NIL || node.color == Black)
+            && node.bh == self.child_bh(l)
+            && node.bh == self.child_bh(r)
+            && node.len as usize == self.len(l) + 1 + self.len(r);
+        Some(Box::new(View::new(
+            self.build_view(l, x),
+            self.build_view(r, x),
+            node.value.as_ptr(),
+            valid,
+            Some(node.color == Red),
+        )))
+    }
+}
+
+#[allow(dead_code)]
+fn pretty<T: Debug>(nodes: &Nodes<T>, root: u32) -> String {
+    fn pretty_recurse<T: Debug>(nodes: &Nodes<T>, x: u32, s: &mut String, depth: usize) {
+        use std::fmt::Write;
+        if x == NIL {
+            return;
+        }
+        let node = &nodes[x];
+        pretty_recurse(nodes, node.left, s, depth + 1);
+        writeln!(
+            s,
+            "{}{:?} {{ index: {x}, parent: {}, len: {}, bh: {}, color: {:?} }}",
+            "  ".repeat(depth),
+            unsafe { node.value.assume_init_ref() },
+            node.parent as i32,
+            node.len,
+            node.bh,
+            node.color,
+        )
+        .unwrap();
+        pretty_recurse(nodes, node.right, s, depth + 1);
+    }
+    if root == NIL {
+        return "(empty)".to_owned();
+    }
+    let mut s = String::new();
+    pretty_recurse(nodes, root, &mut s, 0);
+    assert_eq!(s.pop().unwrap(), '\n');
+    s
+}
+
+impl<T> crate::test_utils::Tree<T> for RbTreeByArena<T> {
+    type Node = View<T>;
+
+    fn root(&self) -> Option<&Self::Node> {
+        self.view
+            .get_or_init(|| self.nodes.build_view(self.root, NIL))
+            .as_deref()
+    }
+}
+
+impl<T> crate::test_utils::Sequence<T> for RbTreeByArena<T> {
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn insert(&mut self, index: usize, value: T) {
+        self.insert(index, value);
+    }
+    fn remove(&mut self, index: usize) -> T {
+        self.remove(index)
+    }
+    fn get(&mut self, index: usize) -> Option<&T> {
+        RbTreeByArena::get(self, index)
+    }
+    fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.get_mut(index)
+    }
+    fn set(&mut self, index: usize, value: T) -> T {
+        self.set(index, value)
+    }
+    fn split_off(&mut self, index: usize) -> Self {
+        self.split_off(index)
+    }
+    fn append(&mut self, other: &mut Self) {
+        self.append(other);
+    }
+    fn insert_slice(&mut self, index: usize, values: &[T])
+    where
+        T: Clone,
+    {
+        self.insert_slice(index, values);
+    }
+    fn remove_range(&mut self, range: Range<usize>) -> Vec<T> {
+        self.remove_range(range)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    fn test_from_iter() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for tid in 1..=test_utils::CASES {
+            eprintln!("==== Case #{tid}");
+            let n = rng.random_range(0..=20);
+            let value_lim = 20;
+            let vec = std::iter::repeat_with(|| rng.random_range(0..value_lim))
+                .take(n)
+                .collect::<Vec<_>>();
+            let tree: RbTreeByArena<i32> = vec.iter().copied().collect();
+            eprintln!("vec = {vec:?}");
+            eprintln!("tree:\n{}", pretty(&tree.nodes, tree.root));
+            test_utils::validate(&tree);
+            eprintln!("tree validated!");
+            assert_eq!(test_utils::collect(&tree), vec);
+        }
+    }
+
+    #[test]
+    fn test_random() {
+        test_utils::check_model::<RbTreeByArena<i32>>();
+    }
+
+    #[test]
+    fn test_remove_range_past_end() {
+        test_utils::check_remove_range_past_end::<RbTreeByArena<i32>>();
+    }
+
+    #[test]
+    fn test_drop() {
+        test_utils::check_drop::<RbTreeByArena<test_utils::Tracked>>();
+    }
+
+    #[test]
+    fn test_split_append() {
+        test_utils::check_split_append::<RbTreeByArena<i32>>();
+    }
+
+    #[test]
+    fn test_compact() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..test_utils::CASES {
+            let n = rng.random_range(0..=30);
+            let mut tree: RbTreeByArena<usize> = (0..n).collect();
+            let mut vec: Vec<usize> = (0..n).collect();
+            for _ in 0..20 {
+                if !vec.is_empty() && rng.random() {
+                    let index = rng.random_range(0..vec.len());
+                    assert_eq!(tree.remove(index), vec.remove(index));
+                } else {
+                    let index = rng.random_range(0..=vec.len());
+                    tree.insert(index, vec.len());
+                    vec.insert(index, vec.len());
+                }
+            }
+            tree.compact();
+            test_utils::validate(&tree);
+            assert_eq!(test_utils::collect(&tree), vec);
+            assert!(tree.nodes.free.is_empty());
+            let indices: Vec<_> = (0..vec.len())
+                .map(|i| tree.nodes.find(tree.root, i))
+                .collect();
+            assert_eq!(indices, (0..vec.len() as u32).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_insert_slice_remove_range() {
+        test_utils::check_insert_slice_remove_range::<RbTreeByArena<i32>>();
+    }
+
+    #[test]
+    fn analyze_tree_stats() {
+        test_utils::analyze_tree_stats::<RbTreeByArena<i32>>();
+    }
+}
@@ -0,0 +1,490 @@
+use crate::stats;
+use crate::xorshift::{xorshift, SEED};
+use std::{
+    mem,
+    ops::Range,
+    ptr::{self, null_mut},
+};
+
+const MAX_LEVEL: usize = 32;
+
+/// An indexable skip list, as a non-tree baseline.
+///
+/// Each link stores its span, the number of positions it skips. The head is at position 0
+/// and element `i` at position `i + 1`. A null link spans to position `len + 1`, so that
+/// `split_off` and `append` can splice spans without walking to the end.
+pub struct SkipList<T> {
+    /// Links of the head. Only the first `levels` are maintained.
+    head: [Link<T>; MAX_LEVEL],
+    levels: usize,
+    len: usize,
+    /// State of the xorshift generator that levels are drawn from.
+    seed: u64,
+}
+impl<T> Default for SkipList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> SkipList<T> {
+    pub fn new() -> Self {
+        Self {
+            head: [Link::END; MAX_LEVEL],
+            levels: 0,
+            len: 0,
+            seed: SEED,
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn insert(&mut self, index: usize, value: T) {
+        assert!(index <= self.len, "index out of range");
+        let level = random_level(&mut self.seed);
+        self.raise(level);
+        stats::allocation();
+        let node = Box::into_raw(Box::new(Node {
+            value,
+            links: vec![Link::END; level].into_boxed_slice(),
+        }));
+        unsafe {
+            let preds = self.preds(index);
+            for (lv, &(link, pos)) in preds[..self.levels].iter().enumerate() {
+                if lv < level {
+                    (*node).links[lv] = Link {
+                        next: (*link).next,
+                        span: pos + (*link).span - index,
+                    };
+                    *link = Link {
+                        next: node,
+                        span: index + 1 - pos,
+                    };
+                } else {
+                    (*link).span += 1;
+                }
+            }
+        }
+        self.len += 1;
+    }
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.len, "index out of range");
+        unsafe {
+            let preds = self.preds(index);
+            let node = (*preds[0].0).next;
+            for (lv, &(link, _)) in preds[..self.levels].iter().enumerate() {
+                if ptr::eq((*link).next, node) {
+                    let next = (*node).links[lv];
+                    *link = Link {
+                        next: next.next,
+                        span: (*link).span + next.span - 1,
+                    };
+                } else {
+                    (*link).span -= 1;
+                }
+            }
+            self.len -= 1;
+            Box::from_raw(node).value
+        }
+    }
+    pub fn get(&self, index: usize) -> Option<&T> {
+        unsafe { self.find(index).as_ref().map(|x| &x.value) }
+    }
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        unsafe { self.find(index).as_mut().map(|x| &mut x.value) }
+    }
+    /// Replaces the element at `index` and returns the old one. Panics if out of range.
+    pub fn set(&mut self, index: usize, value: T) -> T {
+        mem::replace(self.get_mut(index).expect("index out of range"), value)
+    }
+    /// Splits off the elements from `index` on. Panics if `index > len`.
+    pub fn split_off(&mut self, index: usize) -> Self {
+        assert!(index <= self.len, "index out of range");
+        let mut other = Self {
+            head: [Link::END; MAX_LEVEL],
+            levels: self.levels,
+            len: self.len - index,
+            seed: u64::from(xorshift(&mut self.seed)) | 1 << 32,
+        };
+        unsafe {
+            let preds = self.preds(index);
+            for (lv, &(link, pos)) in preds[..self.levels].iter().enumerate() {
+                other.head[lv] = Link {
+                    next: (*link).next,
+                    span: pos + (*link).span - index,
+                };
+                *link = Link {
+                    next: null_mut(),
+                    span: index + 1 - pos,
+                };
+            }
+        }
+        self.len = index;
+        other
+    }
+    /// Moves all elements of `other` to the end of `self`.
+    pub fn append(&mut self, other: &mut Self) {
+        self.raise(other.levels);
+        unsafe {
+            let preds = self.preds(self.len);
+            for (lv, &(link, pos)) in preds[..self.levels].iter().enumerate() {
+                let first = if lv < other.levels {
+                    other.head[lv]
+                } else {
+                    Link {
+                        next: null_mut(),
+                        span: other.len + 1,
+                    }
+                };
+                *link = Link {
+                    next: first.next,
+                    span: self.len - pos + first.span,
+                };
+            }
+        }
+        self.len += mem::take(&mut other.len);
+        other.head = [Link::END; MAX_LEVEL];
+        other.levels = 0;
+    }
+
+    /// Makes at least `level` levels of the head maintained.
+    fn raise(&mut self, level: usize) {
+        while self.levels < level {
+            self.head[self.levels] = Link {
+                next: null_mut(),
+                span: self.len + 1,
+            };
+            self.levels += 1;
+        }
+    }
+
+    /// Returns, for each level, the link out of the last node at a position of at most
+    /// `index`, and that position.
+    unsafe fn preds(&mut self, index: usize) -> [(*mut Link<T>, usize); MAX_LEVEL] {
+        let mut preds = [(null_mut(), 0); MAX_LEVEL];
+        let mut links: *mut [Link<T>] = &raw mut self.head;
+        let mut pos = 0;
+        for lv in (0..self.levels).rev() {
+            loop {
+                let link = (*links)[lv];
+                if link.next.is_null() || pos + link.span > index {
+                    break;
+                }
+                stats::visit();
+                pos += link.span;
+                links = &raw mut *(*link.next).links;
+            }
+            preds[lv] = (&raw mut (*links)[lv], pos);
+        }
+        preds
+    }
+
+    /// Returns the node of the element at `index`, or null if out of range.
+    fn find(&self, index: usize) -> *mut Node<T> {
+        if index >= self.len {
+            return null_mut();
+        }
+        unsafe {
+            let mut links: *const [Link<T>] = &raw const self.head;
+            let mut pos = 0;
+            for lv in (0..self.levels).rev() {
+                loop {
+                    let link = (*links)[lv];
+                    if link.next.is_null() || pos + link.span > index + 1 {
+                        break;
+                    }
+                    stats::visit();
+                    pos += link.span;
+                    if pos == index + 1 {
+                        return link.next;
+                    }
+                    links = &raw const *(*link.next).links;
+                }
+            }
+            unreachable!()
+        }
+    }
+    /// Moves the elements out in order, freeing the nodes.
+    fn into_vec(mut self) -> Vec<T> {
+        let mut values = Vec::with_capacity(self.len);
+        let mut x = mem::replace(&mut self.head[0].next, null_mut());
+        while !x.is_null() {
+            let node = unsafe { Box::from_raw(x) };
+            x = node.links[0].next;
+            values.push(node.value);
+        }
+        values
+    }
+}
+
+crate::range_ops::impl_range_ops!(SkipList);
+
+impl<T> Drop for SkipList<T> {
+    fn drop(&mut self) {
+        let mut x = self.head[0].next;
+        while !x.is_null() {
+            let node = unsafe { Box::from_raw(x) };
+            x = node.links[0].next;
+        }
+    }
+}
+
+impl<T> FromIterator<T> for SkipList<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = Self::new();
+        // The last link at each level and the position it leaves from. Null stands for the
+        // head, which is only borrowed when written to.
+        let mut tails: [(*mut Link<T>, usize); MAX_LEVEL] = [(null_mut(), 0); MAX_LEVEL];
+        for value in iter {
+            let level = random_level(&mut list.seed);
+            list.raise(level);
+            stats::allocation();
+            let node = Box::into_raw(Box::new(Node {
+                value,
+                links: vec![Link::END; level].into_boxed_slice(),
+            }));
+            list.len += 1;
+            for (lv, tail) in tails[..level].iter_mut().enumerate() {
+                let link = Link {
+                    next: node,
+                    span: list.len - tail.1,
+                };
+                if tail.0.is_null() {
+                    list.head[lv] = link;
+                } else {
+                    unsafe { *tail.0 = link };
+                }
+                *tail = (unsafe { &raw mut (*node).links[lv] }, list.len);
+            }
+        }
+        // Close the levels with null links spanning to the end.
+        for (lv, &(link, pos)) in tails[..list.levels].iter().enumerate() {
+            let end = Link {
+                next: null_mut(),
+                span: list.len + 1 - pos,
+            };
+            if link.is_null() {
+                list.head[lv] = end;
+            } else {
+                unsafe { *link = end };
+            }
+        }
+        list
+    }
+}
+
+struct Node<T> {
+    value: T,
+    links: Box<[Link<T>]>,
+}
+
+struct Link<T> {
+    next: *mut Node<T>,
+    span: usize,
+}
+
+impl<T> Clone for Link<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<T> Copy for Link<T> {}
+
+impl<T> Link<T> {
+    /// The null link of the head of an empty list.
+    const END: Self = Self {
+        next: null_mut(),
+        span: 1,
+    };
+}
+
+/// Draws a level from the geometric distribution with ratio 1/2.
+fn random_level(seed: &mut u64) -> usize {
+    (xorshift(seed).trailing_ones() as usize + 1).min(MAX_LEVEL)
+}
+
+impl<T> crate::test_utils::Sequence<T> for SkipList<T> {
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn insert(&mut self, index: usize, value: T) {
+        self.insert(index, value);
+    }
+    fn remove(&mut self, index: usize) -> T {
+        self.remove(index)
+    }
+    fn get(&mut self, index: usize) -> Option<&T> {
+        SkipList::get(self, index)
+    }
+    fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.get_mut(index)
+    }
+    fn set(&mut self, index: usize, value: T) -> T {
+        self.set(index, value)
+    }
+    fn split_off(&mut self, index: usize) -> Self {
+        self.split_off(index)
+    }
+    fn append(&mut self, other: &mut Self) {
+        self.append(other);
+    }
+    fn insert_slice(&mut self, index: usize, values: &[T])
+    where
+        T: Clone,
+    {
+        self.insert_slice(index, values);
+    }
+    fn remove_range(&mut self, range: Range<usize>) -> Vec<T> {
+        self.remove_range(range)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::{self, Query};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::HashMap;
+
+    /// Checks every span against the positions on the bottom level and returns the elements.
+    fn validate<T: Clone>(list: &SkipList<T>) -> Vec<T> {
+        let mut positions = HashMap::new();
+        let mut values = vec![];
+        let mut x = list.head[0].next;
+        while let Some(node) = unsafe { x.as_ref() } {
+            values.push(node.value.clone());
+            positions.insert(x, values.len());
+            x = node.links[0].next;
+        }
+        assert_eq!(values.len(), list.len);
+        for lv in 0..list.levels {
+            let mut link = list.head[lv];
+            let mut pos = 0;
+            while let Some(node) = unsafe { link.next.as_ref() } {
+                let next_pos = positions[&link.next];
+                assert_eq!(link.span, next_pos - pos);
+                assert!(node.links.len() > lv);
+                pos = next_pos;
+                link = node.links[lv];
+            }
+            assert_eq!(link.span, list.len + 1 - pos);
+        }
+        values
+    }
+
+    impl test_utils::Model for SkipList<i32> {
+        fn check(&self) -> Vec<i32> {
+            validate(self)
+        }
+    }
+
+    #[test]
+    fn test_from_iter() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for tid in 1..=test_utils::CASES {
+            eprintln!("==== Case #{tid}");
+            let n = rng.random_range(0..=20);
+            let value_lim = 20;
+            let vec = std::iter::repeat_with(|| rng.random_range(0..value_lim))
+                .take(n)
+                .collect::<Vec<_>>();
+            let list: SkipList<i32> = vec.iter().copied().collect();
+            eprintln!("vec = {vec:?}");
+            assert_eq!(validate(&list), vec);
+            eprintln!("list validated!");
+        }
+    }
+
+    #[test]
+    fn test_random() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for tid in 1..=test_utils::CASES {
+            let q = 200;
+            let len_max = rng.random_range(5..=50);
+            let value_lim = 20;
+            let mut n = 0_usize;
+            let queries: Vec<_> = std::iter::repeat_with(|| {
+                if rng.random_ratio(n as u32, len_max) {
+                    let index = rng.random_range(0..n);
+                    n -= 1;
+                    Query::Remove { index }
+                } else if n > 0 && rng.random_ratio(1, 3) {
+                    let index = rng.random_range(0..n);
+                    let value = rng.random_range(0..value_lim);
+                    Query::Set { index, value }
+                } else if rng.random_ratio(1, 3) {
+                    let index = rng.random_range(0..=n);
+                    Query::Get { index }
+                } else {
+                    let index = rng.random_range(0..=n);
+                    let value = rng.random_range(0..value_lim);
+                    n += 1;
+                    Query::Insert { index, value }
+                }
+            })
+            .take(q)
+            .collect();
+            let mut list = SkipList::new();
+            let mut vec = vec![];
+            for (qid, &query) in (1..).zip(&queries) {
+                eprintln!("==== Case #{tid}.{qid}: {query:?}");
+                match query {
+                    Query::Insert { index, value } => {
+                        list.insert(index, value);
+                        vec.insert(index, value);
+                    }
+                    Query::Remove { index } => {
+                        let result = list.remove(index);
+                        let expected = vec.remove(index);
+                        assert_eq!(result, expected);
+                    }
+                    Query::Get { index } => {
+                        assert_eq!(list.get(index), vec.get(index));
+                    }
+                    Query::Set { index, value } => {
+                        let result = list.set(index, value);
+                        let expected = std::mem::replace(&mut vec[index], value);
+                        assert_eq!(result, expected);
+                    }
+                }
+                eprintln!("vec = {vec:?}");
+                assert_eq!(validate(&list), vec);
+                eprintln!("list validated!");
+                eprintln!();
+            }
+        }
+    }
+
+    #[test]
+    fn test_model() {
+        test_utils::check_model::<SkipList<i32>>();
+    }
+
+    #[test]
+    fn test_remove_range_past_end() {
+        test_utils::check_remove_range_past_end::<SkipList<i32>>();
+    }
+
+    #[test]
+    fn test_drop() {
+        test_utils::check_drop::<SkipList<test_utils::Tracked>>();
+    }
+
+    #[test]
+    fn test_split_append() {
+        test_utils::check_split_append::<SkipList<i32>>();
+    }
+
+    #[test]
+    fn test_insert_slice_remove_range() {
+        test_utils::check_insert_slice_remove_range::<SkipList<i32>>();
+    }
+
+    #[test]
+    fn analyze_tree_stats() {
+        test_utils::analyze_sequence_stats::<SkipList<i32>>();
+    }
+}
@@ -0,0 +1,174 @@
+//! Counters of structural operations, for explaining the timing differences between the
+//! trees.
+//!
+//! The counters are only collected with the `stats` feature. Without it the hooks are empty
+//! and `take` always returns zeros.
+
+#[cfg(feature = "stats")]
+use std::cell::Cell;
+use std::ops::AddAssign;
+
+/// Totals since the last `take`, summed over every tree on the current thread.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Single rotations. A double rotation counts as two.
+    pub rotations: u64,
+    /// Balance checks and repairs on the way back up: AVL and weight-balanced `balance`
+    /// calls, red-black fixup iterations, treap sifts and B-tree splits and underflow fixes.
+    pub rebalance_steps: u64,
+    /// Zig, zig-zig and zig-zag steps.
+    pub splay_steps: u64,
+    /// Nodes allocated: boxes, arena slots, B-tree nodes and skip-list towers.
+    pub allocations: u64,
+    /// Nodes stepped through while searching for a position or a merge point.
+    pub nodes_visited: u64,
+    /// The deepest recursion since the last `take`, so that of a single operation if taken
+    /// after each one. Stays 0 for the iterative implementations.
+    pub max_recursion_depth: u64,
+}
+
+/// Sums the counters and keeps the deeper recursion.
+impl AddAssign for Stats {
+    fn add_assign(&mut self, other: Self) {
+        self.rotations += other.rotations;
+        self.rebalance_steps += other.rebalance_steps;
+        self.splay_steps += other.splay_steps;
+        self.allocations += other.allocations;
+        self.nodes_visited += other.nodes_visited;
+        self.max_recursion_depth = self.max_recursion_depth.max(other.max_recursion_depth);
+    }
+}
+
+/// Whether the crate was built with the `stats` feature.
+pub const ENABLED: bool = cfg!(feature = "stats");
+
+#[cfg(feature = "stats")]
+thread_local! {
+    static STATS: Cell<Stats> = Cell::new(Stats::default());
+    static DEPTH: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Returns the counters and resets them.
+pub fn take() -> Stats {
+    #[cfg(feature = "stats")]
+    return STATS.take();
+    #[cfg(not(feature = "stats"))]
+    Stats::default()
+}
+
+#[inline]
+fn record(f: impl FnOnce(&mut Stats)) {
+    #[cfg(feature = "stats")]
+    STATS.with(|stats| {
+        let mut s = stats.get();
+        f(&mut s);
+        stats.set(s);
+    });
+    #[cfg(not(feature = "stats"))]
+    let _ = f;
+}
+
+#[inline]
+pub(crate) fn rotation() {
+    record(|s| s.rotations += 1);
+}
+
+#[inline]
+pub(crate) fn rebalance_step() {
+    record(|s| s.rebalance_steps += 1);
+}
+
+#[inline]
+pub(crate) fn splay_step() {
+    record(|s| s.splay_steps += 1);
+}
+
+#[inline]
+pub(crate) fn allocation() {
+    record(|s| s.allocations += 1);
+}
+
+#[inline]
+pub(crate) fn visit() {
+    record(|s| s.nodes_visited += 1);
+}
+
+/// Counts one level of recursion until dropped.
+pub(crate) struct Recursion(());
+
+#[inline]
+pub(crate) fn recurse() -> Recursion {
+    #[cfg(feature = "stats")]
+    DEPTH.with(|depth| {
+        depth.set(depth.get() + 1);
+        record(|s| s.max_recursion_depth = s.max_recursion_depth.max(depth.get()));
+    });
+    Recursion(())
+}
+
+#[cfg(feature = "stats")]
+impl Drop for Recursion {
+    fn drop(&mut self) {
+        DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+#[cfg(all(test, feature = "stats"))]
+mod test {
+    use super::*;
+    use crate::avl_tree_by_arena::AvlTreeByArena;
+    use crate::avl_tree_by_box::AvlTreeByBox;
+    use crate::avl_tree_with_parent::AvlTreeWithParent;
+    use crate::splay_tree_with_parent::SplayTreeWithParent;
+
+    #[test]
+    fn test_counters() {
+        take();
+        let mut tree = AvlTreeByBox::new();
+        for i in 0..100 {
+            tree.insert(i, i);
+        }
+        let stats = take();
+        assert_eq!(stats.allocations, 100);
+        assert!(stats.rotations > 0);
+        assert!(stats.rebalance_steps > 0);
+        assert!(stats.nodes_visited > 0);
+        assert!((7..=10).contains(&stats.max_recursion_depth));
+        assert_eq!(take(), Stats::default());
+        tree.remove(50);
+        assert!((7..=10).contains(&take().max_recursion_depth));
+
+        let mut tree = AvlTreeWithParent::new();
+        for i in 0..100 {
+            tree.insert(i, i);
+        }
+        let stats = take();
+        assert_eq!(stats.allocations, 100);
+        assert_eq!(stats.max_recursion_depth, 0);
+
+        let mut tree = SplayTreeWithParent::new();
+        for i in 0..100 {
+            tree.insert(i, i);
+        }
+        take();
+        for i in (0..100).step_by(7) {
+            tree.get(i);
+        }
+        let stats = take();
+        assert!(stats.splay_steps > 0);
+        assert!(stats.rotations >= stats.splay_steps);
+        assert_eq!(stats.allocations, 0);
+        assert_eq!(stats.max_recursion_depth, 0);
+
+        let mut tree = AvlTreeByArena::new();
+        for i in 0..100 {
+            tree.insert(i, i);
+        }
+        let stats = take();
+        assert_eq!(stats.allocations, 100);
+        assert!(stats.rotations > 0);
+        assert_eq!(stats.max_recursion_depth, 0);
+        tree.compact();
+        assert!((7..=10).contains(&take().max_recursion_depth));
+    }
+}
@@ -1,21 +1,43 @@
-use std::ptr;
-
+use crate::{
+    handle::Handle,
+    stats::{self, Stats},
+};
 use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    any,
+    cell::Cell,
+    env, fs,
+    mem::{self, ManuallyDrop},
+    ops::Range,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    ptr,
+    rc::Rc,
+    sync::Once,
+};
 
 #[derive(Debug, Clone, Copy)]
-pub enum Query {
-    Insert { index: usize, value: i32 },
+pub enum Query<T> {
+    Insert { index: usize, value: T },
     Remove { index: usize },
+    Get { index: usize },
+    Set { index: usize, value: T },
 }
 
-pub fn generate_benchmark_queries<T>() -> (T, Vec<Query>)
+/// Number of random cases per test. Miri runs a reduced suite.
+pub const CASES: usize = if cfg!(miri) { 10 } else { 200 };
+
+/// Length of the benchmark workload. Miri runs a reduced suite.
+const BENCHMARK_SIZE: usize = if cfg!(miri) { 200 } else { 200_000 };
+
+pub fn generate_benchmark_queries<T>() -> (T, Vec<Query<i32>>)
 where
     T: FromIterator<i32>,
 {
     let mut rng = StdRng::seed_from_u64(42);
-    let n_initial = 200_000;
-    let len_max = 200_000;
-    let q = 200_000;
+    let n_initial = BENCHMARK_SIZE;
+    let len_max = BENCHMARK_SIZE as u32;
+    let q = BENCHMARK_SIZE;
     let value_lim = 1_000_000_000;
 
     let initial_values: Vec<i32> = (0..n_initial)
@@ -24,7 +46,7 @@ where
     let tree: T = initial_values.into_iter().collect();
 
     let mut n = n_initial;
-    let queries: Vec<Query> = std::iter::repeat_with(|| {
+    let queries: Vec<Query<i32>> = std::iter::repeat_with(|| {
         if rng.random_ratio(n as u32, len_max) {
             let index = rng.random_range(0..n);
             n -= 1;
@@ -42,62 +64,331 @@ where
     (tree, queries)
 }
 
-pub trait TreeNode {
+pub trait TreeNode<T> {
     fn left(&self) -> Option<&Self>;
     fn right(&self) -> Option<&Self>;
-    fn value(&self) -> i32;
+    fn value(&self) -> &T;
+    /// The color of the node, for red-black trees.
+    fn is_red(&self) -> Option<bool> {
+        None
+    }
 }
 
+/// Positional operations, implemented by the trees and by the non-tree baselines.
 #[allow(clippy::len_without_is_empty)]
-pub trait Tree: FromIterator<i32> {
-    type Node: TreeNode;
+pub trait Sequence<T>: FromIterator<T> {
+    fn len(&self) -> usize;
+    fn insert(&mut self, index: usize, value: T);
+    fn remove(&mut self, index: usize) -> T;
+    /// Takes `&mut self` so that self-adjusting trees can restructure on access.
+    fn get(&mut self, index: usize) -> Option<&T>;
+    fn get_mut(&mut self, index: usize) -> Option<&mut T>;
+    fn set(&mut self, index: usize, value: T) -> T;
+    fn split_off(&mut self, index: usize) -> Self;
+    fn append(&mut self, other: &mut Self);
+    fn insert_slice(&mut self, index: usize, values: &[T])
+    where
+        T: Clone;
+    fn remove_range(&mut self, range: Range<usize>) -> Vec<T>;
+}
+
+/// Iteration and handles, implemented by the parent-pointer trees.
+pub trait WithParent<T>: Sequence<T> {
+    type Iter<'a>: DoubleEndedIterator<Item = &'a T> + ExactSizeIterator
+    where
+        Self: 'a,
+        T: 'a;
+
+    fn iter(&self) -> Self::Iter<'_>;
+    fn iter_from(&self, index: usize) -> Self::Iter<'_>;
+    fn insert_with_handle(&mut self, index: usize, value: T) -> Handle;
+    /// Takes `&mut self` so that self-adjusting trees can restructure on access.
+    fn index_of(&mut self, handle: Handle) -> Option<usize>;
+    fn remove_by_handle(&mut self, handle: Handle) -> Option<T>;
+}
+
+pub trait Tree<T>: Sequence<T> {
+    type Node: TreeNode<T>;
 
     fn root(&self) -> Option<&Self::Node>;
-    fn len(&self) -> usize;
-    fn insert(&mut self, index: usize, value: i32);
-    fn remove(&mut self, index: usize) -> i32;
 }
 
-pub fn analyze_tree_stats<T: Tree>() {
+/// Runs the benchmark workload and prints the length, height and node depths as it goes, and
+/// the structural operation counters per query if the `stats` feature is on.
+///
+/// If `BBST_SHAPE_CSV` is set, every `BENCHMARK_SIZE / 50` queries it also measures the
+/// shape, runs a burst of `get`s around the middle and measures the shape again, and writes
+/// one CSV row per checkpoint to `<target dir>/bbst-shape/<tree>.csv`. Only the
+/// self-adjusting trees change shape in the bursts, whose operations are left out of the
+/// counters. The arena trees are measured through views that mirror their nodes one to one.
+pub fn analyze_tree_stats<T: Tree<i32>>() {
+    analyze_stats::<T>(Some(|tree: &T| Shape::of(tree.root())));
+}
+
+/// `analyze_tree_stats` for the sequences without a binary tree shape, which write no CSV.
+pub fn analyze_sequence_stats<T: Sequence<i32>>() {
+    analyze_stats::<T>(None);
+}
+
+/// Number of `get`s in an access burst.
+const BURST: usize = if cfg!(miri) { 10 } else { 1000 };
+
+const CSV_HEADER: &str = "queries,len,height,average_depth,p99_depth,\
+bf_le_-2,bf_-1,bf_0,bf_1,bf_ge_2,red_ratio,\
+burst_height,burst_average_depth,burst_p99_depth";
+
+fn analyze_stats<T: Sequence<i32>>(shape: Option<fn(&T) -> Shape>) {
+    let describe = |tree: &T| match shape {
+        Some(shape) => {
+            let shape = shape(tree);
+            format!(
+                "len={:6}, height={:3}, average depth={:6.2}, p99 depth={:3}",
+                tree.len(),
+                shape.height(),
+                shape.average_depth(),
+                shape.percentile_depth(99),
+            )
+        }
+        None => format!("len={:6}", tree.len()),
+    };
+
     let (mut tree, queries) = generate_benchmark_queries::<T>();
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut csv = shape
+        .filter(|_| env::var_os("BBST_SHAPE_CSV").is_some())
+        .map(|_| vec![CSV_HEADER.to_owned()]);
+    stats::take();
+    let mut totals = Totals::default();
 
     println!("Initial state:");
-    println!("  len={}", tree.len());
+    println!("  {}", describe(&tree));
     println!();
 
     let mut min_len = tree.len();
     let mut max_len = tree.len();
 
-    for (i, &query) in queries.iter().enumerate() {
+    for i in 0..=queries.len() {
+        if let (Some(shape), Some(csv)) = (shape, &mut csv) {
+            if i % (BENCHMARK_SIZE / 50) == 0 {
+                let before = shape(&tree);
+                let window = (tree.len() / 100).max(1);
+                let start = tree.len().saturating_sub(window) / 2;
+                for _ in 0..BURST.min(tree.len()) {
+                    tree.get(start + rng.random_range(0..window));
+                }
+                stats::take();
+                let after = shape(&tree);
+                csv.push(format!(
+                    "{i},{},{},{:.3},{},{},{},{},{:.3},{}",
+                    tree.len(),
+                    before.height(),
+                    before.average_depth(),
+                    before.percentile_depth(99),
+                    before.balance_factors.map(|c| c.to_string()).join(","),
+                    before
+                        .red_ratio()
+                        .map_or(String::new(), |r| format!("{r:.4}")),
+                    after.height(),
+                    after.average_depth(),
+                    after.percentile_depth(99),
+                ));
+            }
+        }
+        let Some(&query) = queries.get(i) else { break };
         match query {
             Query::Insert { index, value } => tree.insert(index, value),
             Query::Remove { index } => {
                 tree.remove(index);
             }
+            Query::Get { index } => {
+                tree.get(index);
+            }
+            Query::Set { index, value } => {
+                tree.set(index, value);
+            }
         }
+        totals.add_query(stats::take());
 
         let len = tree.len();
         min_len = min_len.min(len);
         max_len = max_len.max(len);
 
-        if (i + 1) % 20_000 == 0 {
-            println!("After {:6} queries: len={:6}", i + 1, len);
+        if (i + 1) % (BENCHMARK_SIZE / 10) == 0 {
+            println!("After {:6} queries: {}", i + 1, describe(&tree));
         }
     }
-
     println!();
     println!("Final state:");
-    println!("  len={}", tree.len());
+    println!("  {}", describe(&tree));
     println!();
     println!("Statistics:");
     println!("  Length range: {min_len} - {max_len}");
+    println!();
+    totals.print();
+
+    if let Some(csv) = csv {
+        let path = write_csv::<T>(&csv);
+        println!();
+        println!("Shape written to {}", path.display());
+    }
+}
+
+/// The counters summed over the queries, and the number of queries by recursion depth.
+#[derive(Default)]
+struct Totals {
+    stats: Stats,
+    depths: Vec<u64>,
+}
+
+impl Totals {
+    fn add_query(&mut self, query: Stats) {
+        let depth = query.max_recursion_depth as usize;
+        if self.depths.len() <= depth {
+            self.depths.resize(depth + 1, 0);
+        }
+        self.depths[depth] += 1;
+        self.stats += query;
+    }
+
+    /// The smallest recursion depth that `percent`% of the queries stay within.
+    fn percentile_depth(&self, percent: u64) -> usize {
+        let rank = (self.depths.iter().sum::<u64>() * percent).div_ceil(100);
+        let mut seen = 0;
+        self.depths
+            .iter()
+            .position(|&count| {
+                seen += count;
+                seen >= rank
+            })
+            .unwrap_or(0)
+    }
+
+    fn print(&self) {
+        if !stats::ENABLED {
+            println!("Build with `--features stats` to count structural operations.");
+            return;
+        }
+        let stats = &self.stats;
+        let queries = self.depths.iter().sum::<u64>().max(1) as f64;
+        let per_query = |count: u64| count as f64 / queries;
+        println!("Per query:");
+        println!("  Rotations:       {:8.3}", per_query(stats.rotations));
+        println!(
+            "  Rebalance steps: {:8.3}",
+            per_query(stats.rebalance_steps)
+        );
+        println!("  Splay steps:     {:8.3}", per_query(stats.splay_steps));
+        println!("  Allocations:     {:8.3}", per_query(stats.allocations));
+        println!("  Nodes visited:   {:8.3}", per_query(stats.nodes_visited));
+        let depth_sum: u64 = (0..).zip(&self.depths).map(|(d, &c)| d * c).sum();
+        println!(
+            "  Recursion depth: {:8.3} (p50 {}, p99 {}, max {})",
+            per_query(depth_sum),
+            self.percentile_depth(50),
+            self.percentile_depth(99),
+            stats.max_recursion_depth,
+        );
+    }
+}
+
+/// Writes `bbst-shape/<tree>.csv`, named after the type `T`, in `$CARGO_TARGET_DIR` or else
+/// in the target directory the test binary was built into.
+fn write_csv<T>(rows: &[String]) -> PathBuf {
+    let name = std::any::type_name::<T>();
+    let name = name.split('<').next().unwrap().rsplit("::").next().unwrap();
+    let target = env::var_os("CARGO_TARGET_DIR").map_or_else(
+        || {
+            // The binary is at `<target dir>/<profile>/deps/<name>`.
+            let exe = env::current_exe().unwrap();
+            exe.ancestors().nth(3).unwrap().to_owned()
+        },
+        PathBuf::from,
+    );
+    let dir = target.join("bbst-shape");
+    let path = dir.join(format!("{name}.csv"));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(&path, rows.join("\n") + "\n").unwrap();
+    path
+}
+
+/// The shape of a tree at a checkpoint.
+struct Shape {
+    /// The number of nodes at each depth, the root being at depth 1.
+    depth_counts: Vec<usize>,
+    /// The number of nodes by height of the left subtree minus that of the right, clamped to
+    /// `-2..=2`.
+    balance_factors: [usize; 5],
+    /// The number of red nodes, for red-black trees.
+    red_count: Option<usize>,
+}
+
+impl Shape {
+    fn of<T, N: TreeNode<T>>(root: Option<&N>) -> Self {
+        /// Returns the height of `node`.
+        fn shape_recurse<T, N: TreeNode<T>>(
+            node: Option<&N>,
+            depth: usize,
+            shape: &mut Shape,
+        ) -> usize {
+            let Some(node) = node else { return 0 };
+            if shape.depth_counts.len() <= depth {
+                shape.depth_counts.resize(depth + 1, 0);
+            }
+            shape.depth_counts[depth] += 1;
+            let lh = shape_recurse(node.left(), depth + 1, shape);
+            let rh = shape_recurse(node.right(), depth + 1, shape);
+            shape.balance_factors[((lh as isize - rh as isize).clamp(-2, 2) + 2) as usize] += 1;
+            if let Some(red) = node.is_red() {
+                *shape.red_count.get_or_insert(0) += usize::from(red);
+            }
+            lh.max(rh) + 1
+        }
+        let mut shape = Shape {
+            depth_counts: vec![0],
+            balance_factors: [0; 5],
+            red_count: None,
+        };
+        shape_recurse(root, 1, &mut shape);
+        shape
+    }
+
+    fn len(&self) -> usize {
+        self.depth_counts.iter().sum()
+    }
+
+    fn height(&self) -> usize {
+        self.depth_counts.len() - 1
+    }
+
+    fn average_depth(&self) -> f64 {
+        let depth_sum: usize = (0..).zip(&self.depth_counts).map(|(d, &c)| d * c).sum();
+        depth_sum as f64 / self.len().max(1) as f64
+    }
+
+    /// The smallest depth at or above which `percent`% of the nodes lie.
+    fn percentile_depth(&self, percent: usize) -> usize {
+        let rank = (self.len() * percent).div_ceil(100);
+        let mut seen = 0;
+        (0..)
+            .zip(&self.depth_counts)
+            .find(|&(_, &count)| {
+                seen += count;
+                seen >= rank
+            })
+            .map_or(0, |(depth, _)| depth)
+    }
+
+    fn red_ratio(&self) -> Option<f64> {
+        self.red_count
+            .map(|red| red as f64 / self.len().max(1) as f64)
+    }
 }
 
-pub fn collect<T: Tree>(tree: &T) -> Vec<i32> {
-    fn collect_recurse<N: TreeNode>(node: Option<&N>, out: &mut Vec<i32>) {
+pub fn collect<T: Clone, U: Tree<T>>(tree: &U) -> Vec<T> {
+    fn collect_recurse<T: Clone, N: TreeNode<T>>(node: Option<&N>, out: &mut Vec<T>) {
         let Some(node) = node else { return };
         collect_recurse(node.left(), out);
-        out.push(node.value());
+        out.push(node.value().clone());
         collect_recurse(node.right(), out);
     }
     let mut out = vec![];
@@ -105,7 +396,7 @@ pub fn collect<T: Tree>(tree: &T) -> Vec<i32> {
     out
 }
 
-pub trait Validatable: TreeNode {
+pub trait Validatable<T>: TreeNode<T> {
     const HAS_PARENT_POINTER: bool;
     fn validate_root(&self) -> bool;
     fn validate_balance(&self) -> bool;
@@ -114,11 +405,11 @@ pub trait Validatable: TreeNode {
     }
 }
 
-pub fn validate<T: Tree>(tree: &T)
+pub fn validate<T, U: Tree<T>>(tree: &U)
 where
-    T::Node: Validatable,
+    U::Node: Validatable<T>,
 {
-    fn validate_recurse<N: Validatable>(node: &N, parent: Option<&N>) {
+    fn validate_recurse<T, N: Validatable<T>>(node: &N, parent: Option<&N>) {
         if N::HAS_PARENT_POINTER {
             let expected_parent = node.parent();
             match (expected_parent, parent) {
@@ -139,10 +430,721 @@ where
     }
 
     if let Some(root) = tree.root() {
-        if T::Node::HAS_PARENT_POINTER {
+        if U::Node::HAS_PARENT_POINTER {
             assert!(root.parent().is_none());
         }
         assert!(root.validate_root());
         validate_recurse(root, None);
     }
 }
+
+/// A sequence that `check_model` can compare against a `Vec`.
+pub trait Model: Sequence<i32> {
+    /// Validates the structure and returns the elements in order.
+    fn check(&self) -> Vec<i32>;
+}
+
+impl<T: Tree<i32>> Model for T
+where
+    T::Node: Validatable<i32>,
+{
+    fn check(&self) -> Vec<i32> {
+        validate(self);
+        collect(self)
+    }
+}
+
+/// An operation of a model-based test, applied to both the tree and a `Vec`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Op {
+    Insert { index: usize, value: i32 },
+    Remove { index: usize },
+    Get { index: usize },
+    Set { index: usize, value: i32 },
+    InsertSlice { index: usize, values: Vec<i32> },
+    RemoveRange { start: usize, end: usize },
+    SplitOff { index: usize },
+    Append { values: Vec<i32> },
+}
+
+impl Op {
+    /// Whether the operation is in range for a sequence of length `len`. Shrinking can leave
+    /// operations out of range, which are then skipped.
+    fn is_valid(&self, len: usize) -> bool {
+        match *self {
+            Op::Insert { index, .. }
+            | Op::Get { index }
+            | Op::InsertSlice { index, .. }
+            | Op::SplitOff { index } => index <= len,
+            Op::Remove { index } | Op::Set { index, .. } => index < len,
+            Op::RemoveRange { start, end } => start <= end && end <= len,
+            Op::Append { .. } => true,
+        }
+    }
+
+    /// Candidates for shrinking, each one simpler than `self`.
+    fn simplify(&self) -> Vec<Op> {
+        fn smaller(x: usize) -> Vec<usize> {
+            let mut xs = vec![0, x / 2, x.saturating_sub(1)];
+            xs.dedup();
+            xs.retain(|&y| y < x);
+            xs
+        }
+        fn shorter(values: &[i32]) -> Vec<Vec<i32>> {
+            let mut candidates = vec![];
+            if !values.is_empty() {
+                candidates.push(values[..values.len() / 2].to_vec());
+                candidates.push(values[..values.len() - 1].to_vec());
+            }
+            if let Some(i) = values.iter().position(|&v| v != 0) {
+                let mut zeroed = values.to_vec();
+                zeroed[i] = 0;
+                candidates.push(zeroed);
+            }
+            candidates
+        }
+        let mut candidates = vec![];
+        match *self {
+            Op::Insert { index, value } => {
+                candidates.extend(
+                    smaller(index)
+                        .into_iter()
+                        .map(|index| Op::Insert { index, value }),
+                );
+                if value != 0 {
+                    candidates.push(Op::Insert { index, value: 0 });
+                }
+            }
+            Op::Remove { index } => {
+                candidates.extend(smaller(index).into_iter().map(|index| Op::Remove { index }));
+            }
+            Op::Get { index } => {
+                candidates.extend(smaller(index).into_iter().map(|index| Op::Get { index }));
+            }
+            Op::Set { index, value } => {
+                candidates.extend(
+                    smaller(index)
+                        .into_iter()
+                        .map(|index| Op::Set { index, value }),
+                );
+                if value != 0 {
+                    candidates.push(Op::Set { index, value: 0 });
+                }
+            }
+            Op::InsertSlice { index, ref values } => {
+                if let [value] = values[..] {
+                    candidates.push(Op::Insert { index, value });
+                }
+                candidates.extend(smaller(index).into_iter().map(|index| Op::InsertSlice {
+                    index,
+                    values: values.clone(),
+                }));
+                candidates.extend(
+                    shorter(values)
+                        .into_iter()
+                        .map(|values| Op::InsertSlice { index, values }),
+                );
+            }
+            Op::RemoveRange { start, end } => {
+                if end == start + 1 {
+                    candidates.push(Op::Remove { index: start });
+                }
+                if start < end {
+                    candidates.push(Op::RemoveRange {
+                        start,
+                        end: end - 1,
+                    });
+                    candidates.push(Op::RemoveRange {
+                        start: start + 1,
+                        end,
+                    });
+                }
+                if start > 0 {
+                    candidates.push(Op::RemoveRange {
+                        start: start - 1,
+                        end: end - 1,
+                    });
+                }
+            }
+            Op::SplitOff { index } => {
+                candidates.extend(
+                    smaller(index)
+                        .into_iter()
+                        .map(|index| Op::SplitOff { index }),
+                );
+            }
+            Op::Append { ref values } => {
+                candidates.extend(
+                    shorter(values)
+                        .into_iter()
+                        .map(|values| Op::Append { values }),
+                );
+            }
+        }
+        candidates
+    }
+}
+
+fn generate_ops(rng: &mut impl Rng, len_max: u32, q: usize) -> Vec<Op> {
+    const VALUE_LIM: i32 = 20;
+    fn values(rng: &mut impl Rng) -> Vec<i32> {
+        let k = rng.random_range(0..=5);
+        (0..k).map(|_| rng.random_range(0..VALUE_LIM)).collect()
+    }
+    let mut n = 0_usize;
+    std::iter::repeat_with(|| {
+        if rng.random_ratio((n as u32).min(len_max), len_max) {
+            if rng.random_ratio(1, 4) {
+                let start = rng.random_range(0..n);
+                let end = rng.random_range(start..=n.min(start + 5));
+                n -= end - start;
+                Op::RemoveRange { start, end }
+            } else {
+                let index = rng.random_range(0..n);
+                n -= 1;
+                Op::Remove { index }
+            }
+        } else if n > 0 && rng.random_ratio(1, 3) {
+            let index = rng.random_range(0..n);
+            let value = rng.random_range(0..VALUE_LIM);
+            Op::Set { index, value }
+        } else if rng.random_ratio(1, 3) {
+            let index = rng.random_range(0..=n);
+            Op::Get { index }
+        } else if rng.random_ratio(1, 10) {
+            let index = rng.random_range(0..=n);
+            n = index;
+            Op::SplitOff { index }
+        } else if rng.random_ratio(1, 8) {
+            let values = values(rng);
+            n += values.len();
+            Op::Append { values }
+        } else if rng.random_ratio(1, 6) {
+            let index = rng.random_range(0..=n);
+            let values = values(rng);
+            n += values.len();
+            Op::InsertSlice { index, values }
+        } else {
+            let index = rng.random_range(0..=n);
+            let value = rng.random_range(0..VALUE_LIM);
+            n += 1;
+            Op::Insert { index, value }
+        }
+    })
+    .take(q)
+    .collect()
+}
+
+thread_local! {
+    static QUIET: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Runs `f`, catching a panic and returning its message. The panic is not printed, so that
+/// shrinking does not flood the output.
+fn catch_quietly(f: impl FnOnce()) -> Result<(), String> {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let default = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !QUIET.get() {
+                default(info);
+            }
+        }));
+    });
+    let quiet = QUIET.replace(true);
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    QUIET.set(quiet);
+    result.map_err(|payload| {
+        payload
+            .downcast_ref::<String>()
+            .cloned()
+            .or_else(|| payload.downcast_ref::<&str>().map(|s| (*s).to_owned()))
+            .unwrap_or_default()
+    })
+}
+
+/// Applies the in-range `ops` to a `T` and a `Vec`, validating the tree after each one.
+/// Returns the message of the first mismatch or panic. Trees are leaked rather than dropped
+/// on a panic, since they may be left broken.
+fn run_model<T: Model>(ops: &[Op]) -> Result<(), String> {
+    catch_quietly(|| {
+        let mut tree = ManuallyDrop::new(T::from_iter([]));
+        let mut vec = vec![];
+        for op in ops {
+            if !op.is_valid(vec.len()) {
+                continue;
+            }
+            match *op {
+                Op::Insert { index, value } => {
+                    tree.insert(index, value);
+                    vec.insert(index, value);
+                }
+                Op::Remove { index } => assert_eq!(tree.remove(index), vec.remove(index)),
+                Op::Get { index } => assert_eq!(tree.get(index), vec.get(index)),
+                Op::Set { index, value } => {
+                    assert_eq!(tree.set(index, value), mem::replace(&mut vec[index], value));
+                }
+                Op::InsertSlice { index, ref values } => {
+                    tree.insert_slice(index, values);
+                    vec.splice(index..index, values.iter().copied());
+                }
+                Op::RemoveRange { start, end } => {
+                    let expected: Vec<_> = vec.splice(start..end, []).collect();
+                    assert_eq!(tree.remove_range(start..end), expected);
+                }
+                Op::SplitOff { index } => {
+                    let other = ManuallyDrop::new(tree.split_off(index));
+                    let expected = vec.split_off(index);
+                    assert_eq!(other.check(), expected);
+                    drop(ManuallyDrop::into_inner(other));
+                }
+                Op::Append { ref values } => {
+                    let mut other = ManuallyDrop::new(values.iter().copied().collect::<T>());
+                    assert_eq!(other.check(), *values);
+                    tree.append(&mut other);
+                    vec.extend(values);
+                    assert_eq!(other.len(), 0);
+                    drop(ManuallyDrop::into_inner(other));
+                }
+            }
+            assert_eq!(tree.check(), vec);
+        }
+        drop(ManuallyDrop::into_inner(tree));
+    })
+}
+
+/// Shrinks a failing sequence by deleting runs of operations, from half of them down to
+/// single ones, and by simplifying single operations, until neither makes progress.
+fn shrink(mut ops: Vec<Op>, fails: impl Fn(&[Op]) -> bool) -> Vec<Op> {
+    loop {
+        let mut progress = false;
+        let mut chunk = ops.len() / 2;
+        while chunk > 0 {
+            let mut i = 0;
+            while i + chunk <= ops.len() {
+                let candidate: Vec<_> = [&ops[..i], &ops[i + chunk..]].concat();
+                if fails(&candidate) {
+                    ops = candidate;
+                    progress = true;
+                } else {
+                    i += chunk;
+                }
+            }
+            chunk /= 2;
+        }
+        for i in 0..ops.len() {
+            for simpler in ops[i].simplify() {
+                let mut candidate = ops.clone();
+                candidate[i] = simpler;
+                if fails(&candidate) {
+                    ops = candidate;
+                    progress = true;
+                    break;
+                }
+            }
+        }
+        if !progress {
+            return ops;
+        }
+    }
+}
+
+/// Prints the in-range `ops` as a test that replays them on a `T`, with the results of the
+/// model as the expected values.
+fn reproducer<T>(ops: &[Op]) -> String {
+    fn list(values: &[i32]) -> String {
+        if values.is_empty() {
+            "Vec::<i32>::new()".to_owned()
+        } else {
+            format!("{values:?}")
+        }
+    }
+    let name = any::type_name::<T>().replace(concat!(env!("CARGO_CRATE_NAME"), "::"), "crate::");
+    let mut s = String::new();
+    let mut line = |code: String| {
+        s.push_str("    ");
+        s.push_str(&code);
+        s.push('\n');
+    };
+    line(format!("let mut tree: {name} = [].into_iter().collect();"));
+    let mut vec = vec![];
+    for op in ops {
+        if !op.is_valid(vec.len()) {
+            continue;
+        }
+        match *op {
+            Op::Insert { index, value } => {
+                vec.insert(index, value);
+                line(format!("tree.insert({index}, {value});"));
+            }
+            Op::Remove { index } => {
+                let value = vec.remove(index);
+                line(format!("assert_eq!(tree.remove({index}), {value});"));
+            }
+            Op::Get { index } => {
+                let value = vec
+                    .get(index)
+                    .map_or("None".to_owned(), |v| format!("Some(&{v})"));
+                line(format!("assert_eq!(tree.get({index}), {value});"));
+            }
+            Op::Set { index, value } => {
+                let old = mem::replace(&mut vec[index], value);
+                line(format!("assert_eq!(tree.set({index}, {value}), {old});"));
+            }
+            Op::InsertSlice { index, ref values } => {
+                vec.splice(index..index, values.iter().copied());
+                line(format!("tree.insert_slice({index}, &{values:?});"));
+            }
+            Op::RemoveRange { start, end } => {
+                let removed: Vec<_> = vec.splice(start..end, []).collect();
+                let removed = list(&removed);
+                line(format!(
+                    "assert_eq!(tree.remove_range({start}..{end}), {removed});"
+                ));
+            }
+            Op::SplitOff { index } => {
+                let rest = list(&vec.split_off(index));
+                line(format!("let other = tree.split_off({index});"));
+                line(format!(
+                    "assert_eq!(test_utils::Model::check(&other), {rest});"
+                ));
+            }
+            Op::Append { ref values } => {
+                vec.extend(values);
+                line(format!(
+                    "tree.append(&mut {values:?}.into_iter().collect());"
+                ));
+            }
+        }
+    }
+    line(format!(
+        "assert_eq!(test_utils::Model::check(&tree), {});",
+        list(&vec)
+    ));
+    format!("#[test]\nfn test_reproducer() {{\n{s}}}\n")
+}
+
+/// Runs random operation sequences on a `T` and on a `Vec` model, validating the tree after
+/// each operation. A failing sequence is shrunk to a minimal one, which is printed as a test.
+pub fn check_model<T: Model>() {
+    let mut rng = StdRng::seed_from_u64(42);
+    for _ in 0..CASES {
+        let len_max = rng.random_range(5..=50);
+        let ops = generate_ops(&mut rng, len_max, 200);
+        if run_model::<T>(&ops).is_err() {
+            let ops = shrink(ops, |ops| run_model::<T>(ops).is_err());
+            let message = run_model::<T>(&ops).unwrap_err();
+            panic!(
+                "{message}\n\nMinimal reproducer:\n\n{}",
+                reproducer::<T>(&ops)
+            );
+        }
+    }
+}
+
+/// Checks that `remove_range` past the end panics before the sequence is modified.
+pub fn check_remove_range_past_end<T: Model>() {
+    let mut seq: T = (0..10).collect();
+    let message = catch_quietly(|| drop(seq.remove_range(5..11))).unwrap_err();
+    assert!(message.contains("out of range"), "{message}");
+    assert_eq!(seq.check(), (0..10).collect::<Vec<_>>());
+}
+
+/// Splits a `T` into pieces and appends them back in random order, comparing with `Vec`.
+pub fn check_split_append<T: Model>() {
+    let mut rng = StdRng::seed_from_u64(42);
+    for _ in 0..CASES {
+        let n = rng.random_range(0..=30);
+        let mut seq: T = (0..n).collect();
+        let mut vec: Vec<i32> = (0..n).collect();
+        let mut pieces = vec![];
+        for _ in 0..20 {
+            if !pieces.is_empty() && rng.random() {
+                let (mut other, mut rest): (T, Vec<_>) =
+                    pieces.swap_remove(rng.random_range(0..pieces.len()));
+                seq.append(&mut other);
+                vec.append(&mut rest);
+                assert_eq!(other.len(), 0);
+            } else {
+                let index = rng.random_range(0..=vec.len());
+                pieces.push((seq.split_off(index), vec.split_off(index)));
+            }
+            assert_eq!(seq.check(), vec);
+        }
+        for (other, rest) in &pieces {
+            assert_eq!(other.check(), *rest);
+        }
+    }
+}
+
+/// Runs random `insert_slice`s and `remove_range`s on a `T`, comparing with `Vec::splice`.
+pub fn check_insert_slice_remove_range<T: Model>() {
+    let mut rng = StdRng::seed_from_u64(42);
+    for _ in 0..CASES {
+        let n = rng.random_range(0..=30);
+        let mut seq: T = (0..n).collect();
+        let mut vec: Vec<i32> = (0..n).collect();
+        let mut next = n;
+        for _ in 0..20 {
+            if !vec.is_empty() && rng.random() {
+                let start = rng.random_range(0..=vec.len());
+                let end = rng.random_range(start..=vec.len());
+                let removed: Vec<_> = vec.splice(start..end, []).collect();
+                assert_eq!(seq.remove_range(start..end), removed);
+            } else {
+                let index = rng.random_range(0..=vec.len());
+                let k = rng.random_range(0..=10);
+                let values: Vec<_> = (next..next + k).collect();
+                next += k;
+                vec.splice(index..index, values.iter().copied());
+                seq.insert_slice(index, &values);
+            }
+            assert_eq!(seq.check(), vec);
+        }
+    }
+}
+
+/// A value that counts how many values sharing its counter are alive.
+pub struct Tracked(Rc<Cell<usize>>);
+
+impl Tracked {
+    fn new(live: &Rc<Cell<usize>>) -> Self {
+        live.set(live.get() + 1);
+        Self(Rc::clone(live))
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() - 1);
+    }
+}
+
+/// Builds, reshapes and drops `T`s of `Tracked` values, checking that no value is leaked. A
+/// double drop underflows the counter. Inserting at the front builds a path in the unbalanced
+/// tree, so a recursive drop would overflow the stack.
+pub fn check_drop<T: Sequence<Tracked>>() {
+    let n = if cfg!(miri) { 200 } else { 100_000 };
+    let live = Rc::new(Cell::new(0));
+    let mut seq: T = (0..n).map(|_| Tracked::new(&live)).collect();
+    for _ in 0..n {
+        seq.insert(0, Tracked::new(&live));
+    }
+    drop(seq.remove(n / 2));
+    drop(seq.remove_range(n / 3..n));
+    let other = seq.split_off(n / 4);
+    assert_eq!(live.get(), seq.len() + other.len());
+    drop(other);
+    assert_eq!(live.get(), seq.len());
+    drop(seq);
+    assert_eq!(live.get(), 0);
+}
+
+/// Checks `iter` and `iter_from` from both ends against `Vec`.
+pub fn check_iter<T: WithParent<i32>>() {
+    let mut rng = StdRng::seed_from_u64(42);
+    for _ in 0..CASES {
+        let n = rng.random_range(0..=20);
+        let mut tree = T::from_iter([]);
+        let mut vec = vec![];
+        for value in 0..n {
+            let index = rng.random_range(0..=vec.len());
+            tree.insert(index, value);
+            vec.insert(index, value);
+        }
+        assert!(tree.iter().eq(&vec));
+        assert!(tree.iter().rev().eq(vec.iter().rev()));
+        for index in 0..=vec.len() {
+            assert!(tree.iter_from(index).eq(&vec[index..]));
+            assert!(tree.iter_from(index).rev().eq(vec[index..].iter().rev()));
+        }
+        let mut iter = tree.iter();
+        let mut expected = vec.iter();
+        while let Some(x) = expected.next() {
+            assert_eq!(iter.next(), Some(x));
+            assert_eq!(iter.next_back(), expected.next_back());
+            assert_eq!(iter.len(), expected.len());
+        }
+        assert_eq!(iter.next(), None);
+    }
+}
+
+/// Checks that `index_of` finds every element with a handle, and only those, in `tree`.
+fn check_index_of<T: WithParent<i32>>(tree: &mut T, vec: &[i32], handles: &[Option<Handle>]) {
+    for (value, &handle) in (0..).zip(handles) {
+        if let Some(handle) = handle {
+            let expected = vec.iter().position(|&x| x == value);
+            assert_eq!(tree.index_of(handle), expected);
+        }
+    }
+}
+
+/// Runs random inserts, with and without handles, and removes, by index and by handle.
+pub fn check_handles<T: WithParent<i32> + Model>() {
+    let mut rng = StdRng::seed_from_u64(42);
+    for _ in 0..CASES {
+        let mut tree = T::from_iter([]);
+        let mut vec = vec![];
+        let mut handles = vec![];
+        for _ in 0..50 {
+            if !vec.is_empty() && rng.random_ratio(1, 3) {
+                let index = rng.random_range(0..vec.len());
+                let value = vec.remove(index);
+                match handles[value as usize] {
+                    Some(handle) if rng.random() => {
+                        assert_eq!(tree.remove_by_handle(handle), Some(value));
+                    }
+                    _ => assert_eq!(tree.remove(index), value),
+                }
+            } else {
+                let index = rng.random_range(0..=vec.len());
+                let value = handles.len() as i32;
+                vec.insert(index, value);
+                if rng.random() {
+                    handles.push(Some(tree.insert_with_handle(index, value)));
+                } else {
+                    tree.insert(index, value);
+                    handles.push(None);
+                }
+            }
+        }
+        assert_eq!(tree.check(), vec);
+        check_index_of(&mut tree, &vec, &handles);
+        let mut other = T::from_iter([]);
+        let handle = other.insert_with_handle(0, 0);
+        assert_eq!(tree.index_of(handle), None);
+        assert_eq!(other.index_of(handle), Some(0));
+        assert_eq!(other.remove_by_handle(handle), Some(0));
+        assert_eq!(other.remove_by_handle(handle), None);
+    }
+}
+
+/// Checks that handles follow their elements across `split_off`, `append`, `insert_slice` and
+/// `remove_range`, and that appending a tree with another table invalidates its handles.
+pub fn check_handles_across_splits<T: WithParent<i32> + Model>() {
+    let mut rng = StdRng::seed_from_u64(42);
+    for _ in 0..CASES {
+        let n = rng.random_range(0..=30);
+        let mut tree = T::from_iter([]);
+        let handles: Vec<_> = (0..n)
+            .map(|i| Some(tree.insert_with_handle(i as usize, i)))
+            .collect();
+        let mut vec: Vec<i32> = (0..n).collect();
+        let mut next = n;
+        let mut pieces = vec![];
+        for _ in 0..20 {
+            match rng.random_range(0..3) {
+                0 if !pieces.is_empty() => {
+                    let (mut other, mut rest): (T, Vec<_>) =
+                        pieces.swap_remove(rng.random_range(0..pieces.len()));
+                    tree.append(&mut other);
+                    vec.append(&mut rest);
+                }
+                0 | 1 => {
+                    let index = rng.random_range(0..=vec.len());
+                    pieces.push((tree.split_off(index), vec.split_off(index)));
+                }
+                _ => {
+                    let start = rng.random_range(0..=vec.len());
+                    let end = rng.random_range(start..=vec.len());
+                    let removed: Vec<_> = vec.splice(start..end, [next, next + 1]).collect();
+                    assert_eq!(tree.remove_range(start..end), removed);
+                    tree.insert_slice(start, &[next, next + 1]);
+                    next += 2;
+                }
+            }
+        }
+        assert_eq!(tree.check(), vec);
+        check_index_of(&mut tree, &vec, &handles);
+        for (other, rest) in &mut pieces {
+            assert_eq!(other.check(), *rest);
+            check_index_of(other, rest, &handles);
+        }
+        let mut other = T::from_iter([]);
+        let handle = other.insert_with_handle(0, next);
+        tree.append(&mut other);
+        vec.push(next);
+        assert_eq!(tree.index_of(handle), None);
+        assert_eq!(tree.check(), vec);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `Vec` whose `insert` puts the value one position too far right.
+    struct OffByOne(Vec<i32>);
+
+    impl FromIterator<i32> for OffByOne {
+        fn from_iter<I: IntoIterator<Item = i32>>(iter: I) -> Self {
+            Self(iter.into_iter().collect())
+        }
+    }
+
+    impl Sequence<i32> for OffByOne {
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+        fn insert(&mut self, index: usize, value: i32) {
+            self.0.insert((index + 1).min(self.0.len()), value);
+        }
+        fn remove(&mut self, index: usize) -> i32 {
+            self.0.remove(index)
+        }
+        fn get(&mut self, index: usize) -> Option<&i32> {
+            self.0.get(index)
+        }
+        fn get_mut(&mut self, index: usize) -> Option<&mut i32> {
+            self.0.get_mut(index)
+        }
+        fn set(&mut self, index: usize, value: i32) -> i32 {
+            mem::replace(&mut self.0[index], value)
+        }
+        fn split_off(&mut self, index: usize) -> Self {
+            Self(self.0.split_off(index))
+        }
+        fn append(&mut self, other: &mut Self) {
+            self.0.append(&mut other.0);
+        }
+        fn insert_slice(&mut self, index: usize, values: &[i32]) {
+            self.0.splice(index..index, values.iter().copied());
+        }
+        fn remove_range(&mut self, range: Range<usize>) -> Vec<i32> {
+            self.0.drain(range).collect()
+        }
+    }
+
+    impl Model for OffByOne {
+        fn check(&self) -> Vec<i32> {
+            self.0.clone()
+        }
+    }
+
+    // Every failing run leaks its sequence, which Miri reports.
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_check_model_shrinks() {
+        let message = catch_quietly(check_model::<OffByOne>).unwrap_err();
+        eprintln!("{message}");
+        let (_, reproducer) = message.split_once("Minimal reproducer:").unwrap();
+        let ops: Vec<_> = reproducer
+            .lines()
+            .map(str::trim)
+            .filter(|line| line.starts_with("tree.") || line.starts_with("assert_eq!(tree."))
+            .collect();
+        assert_eq!(ops, ["tree.insert(0, 7);", "tree.insert(0, 0);"]);
+    }
+
+    #[test]
+    fn test_shrink() {
+        let fails = |ops: &[Op]| {
+            ops.iter()
+                .any(|op| matches!(*op, Op::Remove { index } if index >= 3))
+        };
+        let mut rng = StdRng::seed_from_u64(42);
+        let ops = generate_ops(&mut rng, 50, 200);
+        assert!(fails(&ops));
+        assert_eq!(shrink(ops, fails), [Op::Remove { index: 3 }]);
+    }
+}
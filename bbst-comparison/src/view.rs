@@ -0,0 +1,58 @@
+/// A binary tree materialized for `Tree::root`, for structures whose nodes cannot implement
+/// `TreeNode` themselves.
+///
+/// It points into the structure it was built from, which must drop it before any mutation.
+pub struct View<T> {
+    left: Option<Box<Self>>,
+    right: Option<Box<Self>>,
+    value: *const T,
+    /// Whether the invariants of the structure hold where the element is stored.
+    valid: bool,
+    /// The color of the node, for red-black trees.
+    red: Option<bool>,
+}
+
+impl<T> View<T> {
+    pub(crate) fn new(
+        left: Option<Box<Self>>,
+        right: Option<Box<Self>>,
+        value: *const T,
+        valid: bool,
+        red: Option<bool>,
+    ) -> Self {
+        Self {
+            left,
+            right,
+            value,
+            valid,
+            red,
+        }
+    }
+}
+
+impl<T> crate::test_utils::TreeNode<T> for View<T> {
+    fn left(&self) -> Option<&Self> {
+        self.left.as_deref()
+    }
+    fn right(&self) -> Option<&Self> {
+        self.right.as_deref()
+    }
+    fn value(&self) -> &T {
+        // The view is dropped before the structure is mutated.
+        unsafe { &*self.value }
+    }
+    fn is_red(&self) -> Option<bool> {
+        self.red
+    }
+}
+
+impl<T> crate::test_utils::Validatable<T> for View<T> {
+    const HAS_PARENT_POINTER: bool = false;
+
+    fn validate_root(&self) -> bool {
+        true
+    }
+    fn validate_balance(&self) -> bool {
+        self.valid
+    }
+}
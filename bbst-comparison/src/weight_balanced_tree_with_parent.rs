@@ -0,0 +1,648 @@
+use crate::handle::{Handle, RawNode, Slots, NO_SLOT};
+use crate::stats;
+use std::{
+    cmp::Ordering,
+    fmt::Debug,
+    mem,
+    ops::Range,
+    ptr::{self, null_mut},
+};
+
+pub struct WeightBalancedTreeWithParent<T> {
+    root: *mut Node<T>,
+    slots: Slots<Node<T>>,
+}
+impl<T> Default for WeightBalancedTreeWithParent<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> WeightBalancedTreeWithParent<T> {
+    pub fn new() -> Self {
+        Self {
+            root: null_mut(),
+            slots: Slots::new(),
+        }
+    }
+    pub fn len(&self) -> usize {
+        unsafe { self.root.as_ref().map_or(0, |r| r.len) }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn insert(&mut self, index: usize, value: T) {
+        self.insert_node(index, value);
+    }
+    /// Inserts like `insert` and returns a handle to the element.
+    pub fn insert_with_handle(&mut self, index: usize, value: T) -> Handle {
+        let c = self.insert_node(index, value);
+        unsafe { self.slots.issue(c) }
+    }
+    /// Inserts a node without a slot and returns it.
+    fn insert_node(&mut self, index: usize, value: T) -> *mut Node<T> {
+        unsafe {
+            stats::allocation();
+            let c = Box::into_raw(Box::new(Node {
+                parent: null_mut(),
+                left: null_mut(),
+                right: null_mut(),
+                value,
+                slot: NO_SLOT,
+                len: 1,
+            }));
+            let (l, r) = split2(self.root, index);
+            self.root = merge3(l, c, r);
+            c
+        }
+    }
+    pub fn remove(&mut self, index: usize) -> T {
+        unsafe {
+            let (l, c, r) = split3(self.root, index);
+            self.slots.remove((*c).slot);
+            let c = Box::from_raw(c);
+            self.root = merge2(l, r);
+            c.value
+        }
+    }
+    pub fn get(&self, index: usize) -> Option<&T> {
+        unsafe { Node::find(self.root, index).as_ref().map(|x| &x.value) }
+    }
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        unsafe { Node::find(self.root, index).as_mut().map(|x| &mut x.value) }
+    }
+    /// Replaces the element at `index` and returns the old one. Panics if out of range.
+    pub fn set(&mut self, index: usize, value: T) -> T {
+        mem::replace(self.get_mut(index).expect("index out of range"), value)
+    }
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.iter_from(0)
+    }
+    /// Iterates over the elements from `index` on. Panics if `index > len`.
+    pub fn iter_from(&self, index: usize) -> Iter<'_, T> {
+        unsafe { Node::iter_from(self.root, index) }
+    }
+    /// Returns the current index of the element behind `handle`, or `None` if it has been
+    /// removed or belongs to another tree.
+    pub fn index_of(&self, handle: Handle) -> Option<usize> {
+        unsafe { Node::index_of(self.root, &self.slots, handle) }
+    }
+    /// Removes the element behind `handle`, or returns `None` if it has been removed.
+    pub fn remove_by_handle(&mut self, handle: Handle) -> Option<T> {
+        let index = self.index_of(handle)?;
+        Some(self.remove(index))
+    }
+    /// Splits off the elements from `index` on. Panics if `index > len`.
+    pub fn split_off(&mut self, index: usize) -> Self {
+        Self {
+            root: unsafe { Node::split_off(&mut self.root, index) },
+            slots: self.slots.share(),
+        }
+    }
+    /// Moves all elements of `other` to the end of `self`.
+    ///
+    /// Handles into `other` stay valid if both trees share a slot table, which they do if they
+    /// were split off the same tree after it issued a handle. Otherwise they are invalidated,
+    /// which takes time linear in `other.len()`.
+    pub fn append(&mut self, other: &mut Self) {
+        unsafe {
+            Node::append(&mut self.root, &self.slots, &mut other.root, &other.slots);
+        }
+    }
+    /// Moves the elements out in order, freeing the nodes.
+    fn into_vec(mut self) -> Vec<T> {
+        unsafe { self.slots.drain(mem::replace(&mut self.root, null_mut())) }
+    }
+}
+
+crate::range_ops::impl_range_ops!(WeightBalancedTreeWithParent);
+
+impl<T> Drop for WeightBalancedTreeWithParent<T> {
+    fn drop(&mut self) {
+        unsafe { self.slots.free_tree(self.root) };
+    }
+}
+
+impl<T> FromIterator<T> for WeightBalancedTreeWithParent<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        fn from_iter_recurse<T>(values: &mut impl Iterator<Item = T>, n: usize) -> *mut Node<T> {
+            unsafe {
+                if n == 0 {
+                    return null_mut();
+                }
+                let l = from_iter_recurse(values, n / 2);
+                stats::allocation();
+                let c = Box::into_raw(Box::new(Node {
+                    parent: null_mut(),
+                    left: null_mut(),
+                    right: null_mut(),
+                    value: values.next().unwrap(),
+                    slot: NO_SLOT,
+                    len: 0,
+                }));
+                let r = from_iter_recurse(values, n - n / 2 - 1);
+                (*c).left = l;
+                (*c).right = r;
+                if let Some(l) = l.as_mut() {
+                    l.parent = c;
+                }
+                if let Some(r) = r.as_mut() {
+                    r.parent = c;
+                }
+                (*c).update();
+                c
+            }
+        }
+        let values: Vec<_> = iter.into_iter().collect();
+        let n = values.len();
+        let root = from_iter_recurse(&mut values.into_iter(), n);
+        Self {
+            root,
+            slots: Slots::new(),
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a WeightBalancedTreeWithParent<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An in-order iterator that steps through the parent pointers.
+pub type Iter<'a, T> = crate::handle::Iter<'a, T, Node<T>>;
+
+pub struct Node<T> {
+    parent: *mut Self,
+    left: *mut Self,
+    right: *mut Self,
+    value: T,
+    slot: u32,
+    len: usize,
+}
+
+impl<T> RawNode for Node<T> {
+    type Value = T;
+
+    fn children(&mut self) -> (&mut *mut Self, &mut *mut Self) {
+        (&mut self.left, &mut self.right)
+    }
+    fn parent_ptr(&self) -> *mut Self {
+        self.parent
+    }
+    fn left_ptr(&self) -> *mut Self {
+        self.left
+    }
+    fn right_ptr(&self) -> *mut Self {
+        self.right
+    }
+    fn len(&self) -> usize {
+        self.len
+    }
+    fn slot_mut(&mut self) -> &mut u32 {
+        &mut self.slot
+    }
+    fn value(&self) -> &T {
+        &self.value
+    }
+    fn into_value(self) -> T {
+        self.value
+    }
+    unsafe fn split2(x: *mut Self, index: usize) -> (*mut Self, *mut Self) {
+        split2(x, index)
+    }
+    unsafe fn merge2(l: *mut Self, r: *mut Self) -> *mut Self {
+        merge2(l, r)
+    }
+}
+
+impl<T> Node<T> {
+    unsafe fn update(&mut self) {
+        self.len = 1;
+        if let Some(l) = self.left.as_ref() {
+            self.len += l.len;
+        }
+        if let Some(r) = self.right.as_ref() {
+            self.len += r.len;
+        }
+    }
+}
+
+unsafe fn split2<T>(x: *mut Node<T>, index: usize) -> (*mut Node<T>, *mut Node<T>) {
+    if index == x.as_ref().map_or(0, |x| x.len) {
+        return (x, null_mut());
+    }
+    let (l, c, r) = split3(x, index);
+    (l, merge2(c, r))
+}
+
+unsafe fn split3<T>(
+    mut x: *mut Node<T>,
+    mut index: usize,
+) -> (*mut Node<T>, *mut Node<T>, *mut Node<T>) {
+    loop {
+        stats::visit();
+        let llen = (*x).left.as_ref().map_or(0, |x| x.len);
+        match index.cmp(&llen) {
+            Ordering::Less => x = (*x).left,
+            Ordering::Equal => break,
+            Ordering::Greater => {
+                x = (*x).right;
+                index -= llen + 1;
+            }
+        }
+    }
+    while !(*x).parent.is_null() {
+        stats::visit();
+        let p = (*x).parent;
+        if let Some(pp) = (*p).parent.as_mut() {
+            if ptr::eq(pp.left, p) {
+                pp.left = x;
+            } else {
+                pp.right = x;
+            }
+        }
+        (*x).parent = (*p).parent;
+        if ptr::eq((*p).left, x) {
+            (*x).right = merge3((*x).right, p, (*p).right);
+        } else {
+            assert!(ptr::eq((*p).right, x));
+            (*x).left = merge3((*p).left, p, (*x).left);
+        }
+    }
+    let l = (*x).left;
+    let r = (*x).right;
+    (*x).left = null_mut();
+    (*x).right = null_mut();
+    (*x).parent = null_mut();
+    if let Some(l) = l.as_mut() {
+        l.parent = null_mut();
+    }
+    if let Some(r) = r.as_mut() {
+        r.parent = null_mut();
+    }
+    (*x).update();
+    (l, x, r)
+}
+
+unsafe fn merge2<T>(l: *mut Node<T>, r: *mut Node<T>) -> *mut Node<T> {
+    if r.is_null() {
+        return l;
+    }
+    let (_, c, r) = split3(r, 0);
+    merge3(l, c, r)
+}
+
+unsafe fn merge3<T>(mut l: *mut Node<T>, mut c: *mut Node<T>, mut r: *mut Node<T>) -> *mut Node<T> {
+    (*c).parent = null_mut();
+    if let Some(l) = l.as_mut() {
+        l.parent = null_mut();
+    }
+    if let Some(r) = r.as_mut() {
+        r.parent = null_mut();
+    }
+    let mut is_less = false;
+    if DELTA * weight(l) < weight(r) {
+        let mut p = null_mut();
+        while DELTA * weight(l) < weight(r) {
+            stats::visit();
+            p = r;
+            r = (*r).left;
+        }
+        (*c).parent = p;
+        is_less = true;
+    } else if DELTA * weight(r) < weight(l) {
+        let mut p = null_mut();
+        while DELTA * weight(r) < weight(l) {
+            stats::visit();
+            p = l;
+            l = (*l).right;
+        }
+        (*c).parent = p;
+    }
+    (*c).left = l;
+    (*c).right = r;
+    if let Some(l) = (*c).left.as_mut() {
+        l.parent = c;
+    }
+    if let Some(r) = (*c).right.as_mut() {
+        r.parent = c;
+    }
+    if let Some(p) = (*c).parent.as_mut() {
+        if is_less {
+            p.left = c;
+        } else {
+            p.right = c;
+        }
+    }
+    (*c).update();
+    while !(*c).parent.is_null() {
+        c = balance((*c).parent);
+    }
+    c
+}
+
+/// A node is balanced if neither subtree weighs more than `DELTA` times the other.
+const DELTA: usize = 3;
+/// A heavy subtree is fixed by a single rotation if its inner child weighs less than `GAMMA`
+/// times its outer child, and by a double rotation otherwise.
+const GAMMA: usize = 2;
+
+unsafe fn weight<T>(x: *const Node<T>) -> usize {
+    x.as_ref().map_or(0, |x| x.len) + 1
+}
+
+unsafe fn balance<T>(mut x: *mut Node<T>) -> *mut Node<T> {
+    stats::rebalance_step();
+    if DELTA * weight((*x).left) < weight((*x).right) {
+        let r = (*x).right;
+        if weight((*r).left) >= GAMMA * weight((*r).right) {
+            (*x).right = rotate_right(r);
+        }
+        x = rotate_left(x);
+    } else if DELTA * weight((*x).right) < weight((*x).left) {
+        let l = (*x).left;
+        if weight((*l).right) >= GAMMA * weight((*l).left) {
+            (*x).left = rotate_left(l);
+        }
+        x = rotate_right(x);
+    } else {
+        (*x).update();
+    }
+    x
+}
+
+unsafe fn rotate_left<T>(x: *mut Node<T>) -> *mut Node<T> {
+    stats::rotation();
+    let y = (*x).right;
+    (*y).parent = (*x).parent;
+    if let Some(p) = (*y).parent.as_mut() {
+        if ptr::eq(p.left, x) {
+            p.left = y;
+        } else {
+            p.right = y;
+        }
+    }
+    (*x).right = (*y).left;
+    if let Some(c) = (*x).right.as_mut() {
+        c.parent = x;
+    }
+    (*x).parent = y;
+    (*y).left = x;
+    (*x).update();
+    (*y).update();
+    y
+}
+
+unsafe fn rotate_right<T>(x: *mut Node<T>) -> *mut Node<T> {
+    stats::rotation();
+    let y = (*x).left;
+    (*y).parent = (*x).parent;
+    if let Some(p) = (*y).parent.as_mut() {
+        if ptr::eq(p.right, x) {
+            p.right = y;
+        } else {
+            p.left = y;
+        }
+    }
+    (*x).left = (*y).right;
+    if let Some(c) = (*x).left.as_mut() {
+        c.parent = x;
+    }
+    (*x).parent = y;
+    (*y).right = x;
+    (*x).update();
+    (*y).update();
+    y
+}
+
+#[allow(dead_code)]
+fn pretty<T: Debug>(x: *mut Node<T>) -> String {
+    fn pretty_recurse<T: Debug>(x: &Node<T>, s: &mut String, overlines: &mut Vec<bool>) {
+        unsafe {
+            use std::fmt::Write;
+            let dir = match x.parent.as_ref() {
+                Some(p) if ptr::eq(p.left, x) => 1,
+                Some(p) if ptr::eq(p.right, x) => 2,
+                _ => 0,
+            };
+            if let Some(l) = x.left.as_ref() {
+                overlines.push(dir == 2);
+                pretty_recurse(l, s, overlines);
+                overlines.pop().unwrap();
+            }
+            writeln!(
+                s,
+                "{overlines}{corner}{branch} {padding} {value:?} {{ parent: {parent}, left: {left}, right: {right}, len: {len} }}",
+                overlines = overlines
+                    .iter()
+                    .map(|&b| if b { "│" } else { " " })
+                    .collect::<String>(),
+                corner = match dir {
+                    0 => "─",
+                    1 => "┌",
+                    2 => "└",
+                    _ => unreachable!(),
+                },
+                branch = match (x.left.as_ref(), x.right.as_ref()) {
+                    (None, None) => '╴',
+                    (Some(_), None) => '┘',
+                    (None, Some(_)) => '┐',
+                    (Some(_), Some(_)) => '┤',
+                },
+                padding = "┄".repeat(4_usize.saturating_sub(overlines.len())),
+                value = x.value,
+                parent = x.parent.as_ref().map_or_else(||"*".to_owned(), |x| format!("{:?}", x.value)),
+                left = x.left.as_ref().map_or_else(||"*".to_owned(), |x| format!("{:?}", x.value)),
+                right = x.right.as_ref().map_or_else(||"*".to_owned(), |x| format!("{:?}", x.value)),
+                len = x.len,
+            )
+            .unwrap();
+            if let Some(r) = x.right.as_ref() {
+                overlines.push(dir == 1);
+                pretty_recurse(r, s, overlines);
+                overlines.pop().unwrap();
+            }
+        }
+    }
+    unsafe {
+        if let Some(x) = x.as_ref() {
+            let mut s = String::new();
+            pretty_recurse(x, &mut s, &mut vec![]);
+            assert_eq!(s.pop().unwrap(), '\n');
+            s
+        } else {
+            "(empty)".to_owned()
+        }
+    }
+}
+
+impl<T> crate::test_utils::TreeNode<T> for Node<T> {
+    fn left(&self) -> Option<&Self> {
+        unsafe { self.left.as_ref() }
+    }
+    fn right(&self) -> Option<&Self> {
+        unsafe { self.right.as_ref() }
+    }
+    fn value(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> crate::test_utils::Validatable<T> for Node<T> {
+    const HAS_PARENT_POINTER: bool = true;
+
+    fn validate_root(&self) -> bool {
+        true
+    }
+    fn validate_balance(&self) -> bool {
+        unsafe {
+            let (l, r) = (weight(self.left), weight(self.right));
+            DELTA * l >= r && DELTA * r >= l && self.len + 1 == l + r
+        }
+    }
+    fn parent(&self) -> Option<&Self> {
+        unsafe { self.parent.as_ref() }
+    }
+}
+
+impl<T> crate::test_utils::Tree<T> for WeightBalancedTreeWithParent<T> {
+    type Node = Node<T>;
+
+    fn root(&self) -> Option<&Self::Node> {
+        unsafe { self.root.as_ref() }
+    }
+}
+
+impl<T> crate::test_utils::Sequence<T> for WeightBalancedTreeWithParent<T> {
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn insert(&mut self, index: usize, value: T) {
+        self.insert(index, value);
+    }
+    fn remove(&mut self, index: usize) -> T {
+        self.remove(index)
+    }
+    fn get(&mut self, index: usize) -> Option<&T> {
+        WeightBalancedTreeWithParent::get(self, index)
+    }
+    fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.get_mut(index)
+    }
+    fn set(&mut self, index: usize, value: T) -> T {
+        self.set(index, value)
+    }
+    fn split_off(&mut self, index: usize) -> Self {
+        self.split_off(index)
+    }
+    fn append(&mut self, other: &mut Self) {
+        self.append(other);
+    }
+    fn insert_slice(&mut self, index: usize, values: &[T])
+    where
+        T: Clone,
+    {
+        self.insert_slice(index, values);
+    }
+    fn remove_range(&mut self, range: Range<usize>) -> Vec<T> {
+        self.remove_range(range)
+    }
+}
+
+impl<T> crate::test_utils::WithParent<T> for WeightBalancedTreeWithParent<T> {
+    type Iter<'a>
+        = Iter<'a, T>
+    where
+        T: 'a;
+
+    fn iter(&self) -> Iter<'_, T> {
+        self.iter()
+    }
+    fn iter_from(&self, index: usize) -> Iter<'_, T> {
+        self.iter_from(index)
+    }
+    fn insert_with_handle(&mut self, index: usize, value: T) -> Handle {
+        self.insert_with_handle(index, value)
+    }
+    fn index_of(&mut self, handle: Handle) -> Option<usize> {
+        WeightBalancedTreeWithParent::index_of(self, handle)
+    }
+    fn remove_by_handle(&mut self, handle: Handle) -> Option<T> {
+        self.remove_by_handle(handle)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    fn test_from_iter() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for tid in 1..=test_utils::CASES {
+            eprintln!("==== Case #{tid}");
+            let n = rng.random_range(0..=20);
+            let value_lim = 20;
+            let vec = std::iter::repeat_with(|| rng.random_range(0..value_lim))
+                .take(n)
+                .collect::<Vec<_>>();
+            let tree: WeightBalancedTreeWithParent<i32> = vec.iter().copied().collect();
+            eprintln!("vec = {vec:?}");
+            eprintln!("tree\n{}", pretty(tree.root));
+            test_utils::validate(&tree);
+            eprintln!("tree validated!");
+            assert_eq!(test_utils::collect(&tree), vec);
+        }
+    }
+
+    #[test]
+    fn test_random() {
+        test_utils::check_model::<WeightBalancedTreeWithParent<i32>>();
+    }
+
+    #[test]
+    fn test_remove_range_past_end() {
+        test_utils::check_remove_range_past_end::<WeightBalancedTreeWithParent<i32>>();
+    }
+
+    #[test]
+    fn test_drop() {
+        test_utils::check_drop::<WeightBalancedTreeWithParent<test_utils::Tracked>>();
+    }
+
+    #[test]
+    fn test_iter() {
+        test_utils::check_iter::<WeightBalancedTreeWithParent<i32>>();
+    }
+
+    #[test]
+    fn test_handle() {
+        test_utils::check_handles::<WeightBalancedTreeWithParent<i32>>();
+    }
+
+    #[test]
+    fn test_handle_across_splits() {
+        test_utils::check_handles_across_splits::<WeightBalancedTreeWithParent<i32>>();
+    }
+
+    #[test]
+    fn test_split_append() {
+        test_utils::check_split_append::<WeightBalancedTreeWithParent<i32>>();
+    }
+
+    #[test]
+    fn test_insert_slice_remove_range() {
+        test_utils::check_insert_slice_remove_range::<WeightBalancedTreeWithParent<i32>>();
+    }
+
+    #[test]
+    fn analyze_tree_stats() {
+        test_utils::analyze_tree_stats::<WeightBalancedTreeWithParent<i32>>();
+    }
+}
@@ -0,0 +1,10 @@
+/// The initial state of every generator, so that runs are reproducible.
+pub(crate) const SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+/// Advances the xorshift64 state `seed` and returns the upper half of the new state.
+pub(crate) fn xorshift(seed: &mut u64) -> u32 {
+    *seed ^= *seed << 13;
+    *seed ^= *seed >> 7;
+    *seed ^= *seed << 17;
+    (*seed >> 32) as u32
+}
@@ -1,53 +1,132 @@
 mod common;
 
 use bitvector_rank::{
-    Rank1, Rank64, Rank64Interlaced, Rank25664, Rank25664Interlaced, Rank51264Interlaced,
+    HybridBitvector, Rank1, Rank64, Rank64Interlaced, Rank25664, Rank25664Interlaced,
+    Rank51264Interlaced,
 };
-use common::{TestCase, bench_construct, bench_rank};
-use criterion::{Criterion, criterion_group, criterion_main};
-use rand::{SeedableRng, rngs::StdRng};
+use common::{Query, SCENARIOS, TestCase, bench_construct, bench_rank};
+use criterion::measurement::WallTime;
+use criterion::{BenchmarkGroup, Criterion, black_box, criterion_group, criterion_main};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use std::time::{Duration, Instant};
 
-fn bench_bitvector_construct(c: &mut Criterion) {
-    let mut group = c.benchmark_group("Bitvector Construct");
-
-    let mut rng = StdRng::seed_from_u64(42);
-    let TestCase { a, queries: _ } = TestCase::generate(&mut rng);
+/// Length of the bitvector in the parallel construction benchmark.
+const PAR_LEN: usize = 1_000_000_000;
 
-    bench_construct::<Rank1>(&mut group, "Rank1", &a);
-    bench_construct::<Rank64>(&mut group, "Rank64", &a);
-    bench_construct::<Rank25664>(&mut group, "Rank25664", &a);
-    bench_construct::<Rank64Interlaced>(&mut group, "Rank64Interlaced", &a);
-    bench_construct::<Rank25664Interlaced>(&mut group, "Rank25664Interlaced", &a);
-    bench_construct::<Rank51264Interlaced>(&mut group, "Rank51264Interlaced", &a);
+fn construct_all(group: &mut BenchmarkGroup<WallTime>, a: &[bool]) {
+    bench_construct::<Rank1>(group, "Rank1", a);
+    bench_construct::<Rank64>(group, "Rank64", a);
+    bench_construct::<Rank64<u32>>(group, "Rank64<u32>", a);
+    bench_construct::<Rank25664>(group, "Rank25664", a);
+    bench_construct::<Rank25664<u32>>(group, "Rank25664<u32>", a);
+    bench_construct::<Rank64Interlaced>(group, "Rank64Interlaced", a);
+    bench_construct::<Rank25664Interlaced>(group, "Rank25664Interlaced", a);
+    bench_construct::<Rank51264Interlaced>(group, "Rank51264Interlaced", a);
 
-    group.finish();
+    bench_construct::<HybridBitvector>(group, "HybridBitvector", a);
 }
 
-fn bench_bitvector_rank(c: &mut Criterion) {
-    let mut group = c.benchmark_group("Bitvector Rank");
-    let mut rng = StdRng::seed_from_u64(42);
-    let TestCase { a, queries } = TestCase::generate(&mut rng);
-
+fn rank_all(group: &mut BenchmarkGroup<WallTime>, a: &[bool], queries: &[Query]) {
     let bvec: Rank1 = a.iter().copied().collect();
-    bench_rank(&mut group, "Rank1", &bvec, &queries);
+    bench_rank(group, "Rank1", &bvec, queries);
 
     let bvec: Rank64 = a.iter().copied().collect();
-    bench_rank(&mut group, "Rank64", &bvec, &queries);
+    bench_rank(group, "Rank64", &bvec, queries);
+
+    let bvec: Rank64<u32> = a.iter().copied().collect();
+    bench_rank(group, "Rank64<u32>", &bvec, queries);
 
     let bvec: Rank25664 = a.iter().copied().collect();
-    bench_rank(&mut group, "Rank25664", &bvec, &queries);
+    bench_rank(group, "Rank25664", &bvec, queries);
+
+    let bvec: Rank25664<u32> = a.iter().copied().collect();
+    bench_rank(group, "Rank25664<u32>", &bvec, queries);
 
     let bvec: Rank64Interlaced = a.iter().copied().collect();
-    bench_rank(&mut group, "Rank64Interlaced", &bvec, &queries);
+    bench_rank(group, "Rank64Interlaced", &bvec, queries);
 
     let bvec: Rank25664Interlaced = a.iter().copied().collect();
-    bench_rank(&mut group, "Rank25664Interlaced", &bvec, &queries);
+    bench_rank(group, "Rank25664Interlaced", &bvec, queries);
 
     let bvec: Rank51264Interlaced = a.iter().copied().collect();
-    bench_rank(&mut group, "Rank51264Interlaced", &bvec, &queries);
+    bench_rank(group, "Rank51264Interlaced", &bvec, queries);
+
+    let bvec: HybridBitvector = a.iter().copied().collect();
+    bench_rank(group, "HybridBitvector", &bvec, queries);
+}
+
+fn bench_bitvector_construct(c: &mut Criterion) {
+    for scenario in SCENARIOS {
+        let mut group = c.benchmark_group(format!("Bitvector Construct/{}", scenario.name));
+        let mut rng = StdRng::seed_from_u64(42);
+        let TestCase { a, queries: _ } = TestCase::from_scenario(&mut rng, scenario);
+        construct_all(&mut group, &a);
+        group.finish();
+    }
+}
+
+/// Compares the sequential and parallel builds of a 10^9-bit `Rank51264Interlaced` and
+/// prints the speedup of `par_from_words` over `from_words`, taking the best of three runs
+/// of each. `from_iter` is there for reference.
+fn bench_par_construct(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(42);
+    let words: Vec<u64> = std::iter::repeat_with(|| rng.random())
+        .take(PAR_LEN / 64)
+        .collect();
+    let sequential = || Rank51264Interlaced::from_words(&words, PAR_LEN);
+    let parallel = || Rank51264Interlaced::par_from_words(&words, PAR_LEN);
 
+    let mut group = c.benchmark_group("Bitvector Construct/1e9");
+    group.sample_size(10);
+    group.bench_function("Rank51264Interlaced", |b| {
+        b.iter(|| {
+            black_box(
+                (0..PAR_LEN)
+                    .map(|i| words[i / 64] >> (i % 64) & 1 == 1)
+                    .collect::<Rank51264Interlaced>(),
+            )
+        });
+    });
+    group.bench_function("Rank51264Interlaced::from_words", |b| {
+        b.iter(|| black_box(sequential()));
+    });
+    group.bench_function("Rank51264Interlaced::par_from_words", |b| {
+        b.iter(|| black_box(parallel()));
+    });
     group.finish();
+
+    let best = |build: &dyn Fn() -> Rank51264Interlaced| -> Duration {
+        (0..3)
+            .map(|_| {
+                let start = Instant::now();
+                black_box(build());
+                start.elapsed()
+            })
+            .min()
+            .unwrap()
+    };
+    let (seq, par) = (best(&sequential), best(&parallel));
+    let threads = std::thread::available_parallelism().map_or(1, std::num::NonZero::get);
+    println!(
+        "par_from_words speedup: {:.2}x ({seq:?} -> {par:?}, {threads} threads)",
+        seq.as_secs_f64() / par.as_secs_f64()
+    );
+}
+
+fn bench_bitvector_rank(c: &mut Criterion) {
+    for scenario in SCENARIOS {
+        let mut group = c.benchmark_group(format!("Bitvector Rank/{}", scenario.name));
+        let mut rng = StdRng::seed_from_u64(42);
+        let TestCase { a, queries } = TestCase::from_scenario(&mut rng, scenario);
+        rank_all(&mut group, &a, &queries);
+        group.finish();
+    }
 }
 
-criterion_group!(benches, bench_bitvector_construct, bench_bitvector_rank);
+criterion_group!(
+    benches,
+    bench_bitvector_construct,
+    bench_par_construct,
+    bench_bitvector_rank
+);
 criterion_main!(benches);
@@ -8,28 +8,201 @@ pub enum Query {
     Rank { index: usize },
 }
 
+/// How the bits of a test vector are laid out.
+#[derive(Clone, Copy)]
+pub enum Bits {
+    /// Each bit is one with probability `p`.
+    Uniform { p: f64 },
+    /// Runs of geometric length with mean `mean_run`, each of a random value: a run is ones
+    /// with probability `p`, independently of its neighbours, so two runs in a row may merge.
+    Runs { p: f64, mean_run: f64 },
+    /// Every bit is the same.
+    Constant(bool),
+    /// Segments of geometric length with mean `mean_segment`, each drawn from
+    /// [`MIXED_SEGMENTS`].
+    Mixed { mean_segment: f64 },
+}
+
+/// Segment kinds of [`Bits::Mixed`].
+pub const MIXED_SEGMENTS: &[Bits] = &[
+    Bits::Uniform { p: 0.5 },
+    Bits::Uniform { p: 0.001 },
+    Bits::Runs {
+        p: 0.5,
+        mean_run: 4096.0,
+    },
+    Bits::Constant(false),
+    Bits::Constant(true),
+];
+
+/// Where rank queries land.
+#[derive(Clone, Copy)]
+pub enum Locality {
+    Uniform,
+    /// `0, 1, 2, ...`, wrapping around.
+    Sequential,
+    /// `0, stride, 2 * stride, ...` modulo `n + 1`.
+    Strided {
+        stride: usize,
+    },
+    /// Picks one of `spots` hot spots with Zipf exponent `s`, then a uniform offset within
+    /// `width` bits of it.
+    Zipf {
+        spots: usize,
+        s: f64,
+        width: usize,
+    },
+}
+
+pub struct Scenario {
+    pub name: &'static str,
+    pub bits: Bits,
+    pub locality: Locality,
+}
+
+pub const SCENARIOS: &[Scenario] = &[
+    Scenario {
+        name: "uniform",
+        bits: Bits::Uniform { p: 0.5 },
+        locality: Locality::Uniform,
+    },
+    Scenario {
+        name: "sparse",
+        bits: Bits::Uniform { p: 0.01 },
+        locality: Locality::Uniform,
+    },
+    Scenario {
+        name: "dense",
+        bits: Bits::Uniform { p: 0.99 },
+        locality: Locality::Uniform,
+    },
+    Scenario {
+        name: "runs",
+        bits: Bits::Runs {
+            p: 0.5,
+            mean_run: 4096.0,
+        },
+        locality: Locality::Uniform,
+    },
+    Scenario {
+        name: "mixed",
+        bits: Bits::Mixed {
+            mean_segment: 262_144.0,
+        },
+        locality: Locality::Uniform,
+    },
+    Scenario {
+        name: "all-zero",
+        bits: Bits::Constant(false),
+        locality: Locality::Uniform,
+    },
+    Scenario {
+        name: "all-one",
+        bits: Bits::Constant(true),
+        locality: Locality::Uniform,
+    },
+    Scenario {
+        name: "sequential",
+        bits: Bits::Uniform { p: 0.5 },
+        locality: Locality::Sequential,
+    },
+    Scenario {
+        name: "strided",
+        bits: Bits::Uniform { p: 0.5 },
+        locality: Locality::Strided { stride: 4099 },
+    },
+    Scenario {
+        name: "zipf",
+        bits: Bits::Uniform { p: 0.5 },
+        locality: Locality::Zipf {
+            spots: 1000,
+            s: 1.0,
+            width: 4096,
+        },
+    },
+];
+
 pub struct TestCase {
     pub a: Vec<bool>,
     pub queries: Vec<Query>,
 }
 
 impl TestCase {
-    pub fn generate(rng: &mut impl Rng) -> Self {
+    pub fn from_scenario(rng: &mut impl Rng, scenario: &Scenario) -> Self {
         let n = 100_000_000;
-        let a: Vec<_> = std::iter::repeat_with(|| rng.random_ratio(1, 2))
-            .take(n)
-            .collect();
         let q = 10_000_000;
-        let queries = std::iter::repeat_with(|| {
-            let index = rng.random_range(0..=n);
-            Query::Rank { index }
-        })
-        .take(q)
-        .collect();
+        let a = generate_bits(rng, scenario.bits, n);
+        let queries = generate_indices(rng, scenario.locality, n, q)
+            .into_iter()
+            .map(|index| Query::Rank { index })
+            .collect();
         Self { a, queries }
     }
 }
 
+pub fn generate_bits(rng: &mut impl Rng, bits: Bits, n: usize) -> Vec<bool> {
+    match bits {
+        Bits::Uniform { p } => std::iter::repeat_with(|| rng.random_bool(p))
+            .take(n)
+            .collect(),
+        Bits::Runs { p, mean_run } => {
+            let mut a = Vec::with_capacity(n);
+            while a.len() < n {
+                let b = rng.random_bool(p);
+                let run = geometric(rng, mean_run).min(n - a.len());
+                a.extend(std::iter::repeat_n(b, run));
+            }
+            a
+        }
+        Bits::Constant(b) => vec![b; n],
+        Bits::Mixed { mean_segment } => {
+            let mut a = Vec::with_capacity(n);
+            while a.len() < n {
+                let len = geometric(rng, mean_segment).min(n - a.len());
+                let bits = MIXED_SEGMENTS[rng.random_range(0..MIXED_SEGMENTS.len())];
+                a.extend(generate_bits(rng, bits, len));
+            }
+            a
+        }
+    }
+}
+
+/// Samples a geometric length with the given mean.
+fn geometric(rng: &mut impl Rng, mean: f64) -> usize {
+    let u: f64 = rng.random();
+    1 + ((1.0 - u).ln() / (1.0 - 1.0 / mean).ln()) as usize
+}
+
+/// Generates `q` indices in `0..=n`.
+pub fn generate_indices(rng: &mut impl Rng, locality: Locality, n: usize, q: usize) -> Vec<usize> {
+    match locality {
+        Locality::Uniform => std::iter::repeat_with(|| rng.random_range(0..=n))
+            .take(q)
+            .collect(),
+        Locality::Sequential => (0..q).map(|i| i % (n + 1)).collect(),
+        Locality::Strided { stride } => (0..q).map(|i| i * stride % (n + 1)).collect(),
+        Locality::Zipf { spots, s, width } => {
+            let centers: Vec<usize> = std::iter::repeat_with(|| rng.random_range(0..=n))
+                .take(spots)
+                .collect();
+            let mut cumulative = Vec::with_capacity(spots);
+            let mut total = 0.0;
+            for k in 1..=spots {
+                total += 1.0 / (k as f64).powf(s);
+                cumulative.push(total);
+            }
+            std::iter::repeat_with(|| {
+                let x = rng.random_range(0.0..total);
+                let k = cumulative.partition_point(|&c| c <= x).min(spots - 1);
+                let offset = rng.random_range(0..width);
+                (centers[k] + offset).min(n)
+            })
+            .take(q)
+            .collect()
+        }
+    }
+}
+
 pub fn bench_construct<T: FromIterator<bool>>(
     group: &mut BenchmarkGroup<WallTime>,
     name: &str,
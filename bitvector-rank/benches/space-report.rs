@@ -0,0 +1,66 @@
+//! Prints bits per input bit next to construct and rank timings for every layout and
+//! scenario.
+
+#[allow(dead_code)]
+mod common;
+
+use bitvector_rank::test_utils::RankDataStructure;
+use bitvector_rank::{
+    HybridBitvector, Rank1, Rank64, Rank64Interlaced, Rank25664, Rank25664Interlaced,
+    Rank51264Interlaced,
+};
+use common::{Query, SCENARIOS, TestCase};
+use criterion::black_box;
+use rand::{SeedableRng, rngs::StdRng};
+use std::time::{Duration, Instant};
+
+fn report<T: RankDataStructure>(name: &str, a: &[bool], queries: &[Query]) {
+    let start = Instant::now();
+    let bvec: T = a.iter().copied().collect();
+    let construct = start.elapsed();
+    let start = Instant::now();
+    for &Query::Rank { index } in queries {
+        black_box(bvec.rank(index));
+    }
+    let rank = start.elapsed();
+    let space = bvec.space_breakdown();
+    println!(
+        "{name:<20} {:>8.4} {:>12} {:>12} {:>12} {:>10} {:>10}",
+        space.bits_per_bit(a.len()),
+        space.words,
+        space.block,
+        space.sblock,
+        ms(construct),
+        ms(rank),
+    );
+}
+
+fn ms(d: Duration) -> String {
+    format!("{:.1} ms", d.as_secs_f64() * 1e3)
+}
+
+fn main() {
+    for scenario in SCENARIOS {
+        let mut rng = StdRng::seed_from_u64(42);
+        let TestCase { a, queries } = TestCase::from_scenario(&mut rng, scenario);
+        println!();
+        println!("{}: N = {}, Q = {}", scenario.name, a.len(), queries.len());
+        report_all(&a, &queries);
+    }
+}
+
+fn report_all(a: &[bool], queries: &[Query]) {
+    println!(
+        "{:<20} {:>8} {:>12} {:>12} {:>12} {:>10} {:>10}",
+        "layout", "bit/bit", "words [B]", "block [B]", "sblock [B]", "construct", "rank"
+    );
+    report::<Rank1>("Rank1", a, queries);
+    report::<Rank64>("Rank64", a, queries);
+    report::<Rank64<u32>>("Rank64<u32>", a, queries);
+    report::<Rank25664>("Rank25664", a, queries);
+    report::<Rank25664<u32>>("Rank25664<u32>", a, queries);
+    report::<Rank64Interlaced>("Rank64Interlaced", a, queries);
+    report::<Rank25664Interlaced>("Rank25664Interlaced", a, queries);
+    report::<Rank51264Interlaced>("Rank51264Interlaced", a, queries);
+    report::<HybridBitvector>("HybridBitvector", a, queries);
+}
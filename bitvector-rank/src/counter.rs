@@ -0,0 +1,44 @@
+use core::fmt;
+
+/// Unsigned integer type used to store cumulative counts in a rank directory.
+pub trait Counter: Copy {
+    /// Converts a count, or returns `None` if it does not fit.
+    fn from_u64(x: u64) -> Option<Self>;
+    fn to_u64(self) -> u64;
+}
+
+macro_rules! impl_counter {
+    ($($t:ty),*) => {$(
+        impl Counter for $t {
+            fn from_u64(x: u64) -> Option<Self> {
+                Self::try_from(x).ok()
+            }
+            fn to_u64(self) -> u64 {
+                self.into()
+            }
+        }
+    )*};
+}
+impl_counter!(u8, u16, u32, u64);
+
+/// A cumulative count did not fit in the counter type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CounterOverflow {
+    pub count: u64,
+}
+
+impl fmt::Display for CounterOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cumulative count {} overflows the rank counter",
+            self.count
+        )
+    }
+}
+
+impl core::error::Error for CounterOverflow {}
+
+pub(crate) fn to_counter<C: Counter>(count: u64) -> Result<C, CounterOverflow> {
+    C::from_u64(count).ok_or(CounterOverflow { count })
+}
@@ -0,0 +1,235 @@
+use crate::{Rank51264Interlaced, RankDataStructure, SpaceBreakdown};
+use alloc::{boxed::Box, vec, vec::Vec};
+
+/// Bits per chunk.
+const CHUNK: usize = 1 << 16;
+
+/// A bitvector that stores each $2^{16}$-bit chunk in whichever of three encodings is the
+/// smallest: raw words with [`Rank51264Interlaced`] counters, sorted positions of the
+/// ones, or run boundaries.
+pub struct HybridBitvector {
+    len: usize,
+    /// Ones before each chunk, followed by the total.
+    chunk_rank: Vec<u64>,
+    chunks: Vec<Chunk>,
+}
+
+enum Chunk {
+    Dense(Rank51264Interlaced),
+    /// Offsets of the ones.
+    Sparse(Box<[u16]>),
+    /// `starts[r]` is the offset where run `r + 1` begins and `ones[r]` the ones before it.
+    /// Runs alternate, and the first one is `first`.
+    Runs {
+        first: bool,
+        starts: Box<[u16]>,
+        ones: Box<[u16]>,
+    },
+}
+
+impl HybridBitvector {
+    pub fn rank(&self, index: usize) -> usize {
+        assert!(index <= self.len);
+        let c = index / CHUNK;
+        let local = self
+            .chunks
+            .get(c)
+            .map_or(0, |chunk| chunk.rank(index % CHUNK));
+        self.chunk_rank[c] as usize + local
+    }
+
+    /// Returns the position of the `k`-th one (0-indexed).
+    pub fn select(&self, k: usize) -> usize {
+        assert!(
+            (k as u64) < *self.chunk_rank.last().unwrap(),
+            "select out of range"
+        );
+        let c = self.chunk_rank.partition_point(|&r| r <= k as u64) - 1;
+        c * CHUNK + self.chunks[c].select(k - self.chunk_rank[c] as usize)
+    }
+
+    /// Number of chunks stored as `(dense, sparse, runs)`.
+    pub fn encoding_counts(&self) -> (usize, usize, usize) {
+        let mut counts = (0, 0, 0);
+        for chunk in &self.chunks {
+            match chunk {
+                Chunk::Dense(_) => counts.0 += 1,
+                Chunk::Sparse(_) => counts.1 += 1,
+                Chunk::Runs { .. } => counts.2 += 1,
+            }
+        }
+        counts
+    }
+}
+
+impl Chunk {
+    fn new(bits: &[bool]) -> Self {
+        let ones = bits.iter().filter(|&&b| b).count();
+        let flips = bits.windows(2).filter(|w| w[0] != w[1]).count();
+        let dense = 8 * (bits.len() / 64 + 1 + 2 * (bits.len() / 512 + 1));
+        if 2 * ones <= dense.min(4 * flips) {
+            let pos = (0..bits.len()).filter(|&i| bits[i]).map(|i| i as u16);
+            Chunk::Sparse(pos.collect())
+        } else if 4 * flips < dense {
+            let mut starts = Vec::with_capacity(flips);
+            let mut ones = Vec::with_capacity(flips);
+            let mut count = 0;
+            for i in 1..bits.len() {
+                count += u16::from(bits[i - 1]);
+                if bits[i] != bits[i - 1] {
+                    starts.push(i as u16);
+                    ones.push(count);
+                }
+            }
+            Chunk::Runs {
+                first: bits[0],
+                starts: starts.into(),
+                ones: ones.into(),
+            }
+        } else {
+            Chunk::Dense(bits.iter().copied().collect())
+        }
+    }
+
+    fn rank(&self, offset: usize) -> usize {
+        match self {
+            Chunk::Dense(bvec) => bvec.rank(offset),
+            Chunk::Sparse(pos) => pos.partition_point(|&p| usize::from(p) < offset),
+            Chunk::Runs {
+                first,
+                starts,
+                ones,
+            } => {
+                let r = starts.partition_point(|&s| usize::from(s) <= offset);
+                if r == 0 {
+                    return if *first { offset } else { 0 };
+                }
+                let (start, before) = (usize::from(starts[r - 1]), usize::from(ones[r - 1]));
+                if *first ^ (r % 2 == 1) {
+                    before + offset - start
+                } else {
+                    before
+                }
+            }
+        }
+    }
+
+    fn select(&self, k: usize) -> usize {
+        match self {
+            Chunk::Dense(bvec) => bvec.select(k),
+            Chunk::Sparse(pos) => usize::from(pos[k]),
+            Chunk::Runs { starts, ones, .. } => {
+                let r = ones.partition_point(|&o| usize::from(o) <= k);
+                if r == 0 {
+                    return k;
+                }
+                usize::from(starts[r - 1]) + k - usize::from(ones[r - 1])
+            }
+        }
+    }
+
+    fn space_breakdown(&self) -> SpaceBreakdown {
+        match self {
+            Chunk::Dense(bvec) => bvec.space_breakdown(),
+            Chunk::Sparse(pos) => SpaceBreakdown {
+                words: size_of_val(&**pos),
+                block: 0,
+                sblock: 0,
+            },
+            Chunk::Runs { starts, ones, .. } => SpaceBreakdown {
+                words: size_of_val(&**starts) + size_of_val(&**ones),
+                block: 0,
+                sblock: 0,
+            },
+        }
+    }
+}
+
+impl RankDataStructure for HybridBitvector {
+    fn rank(&self, index: usize) -> usize {
+        self.rank(index)
+    }
+
+    /// Chunk payloads count as words; the chunk directory counts as superblock counters.
+    fn space_breakdown(&self) -> SpaceBreakdown {
+        let mut space = SpaceBreakdown {
+            words: 0,
+            block: 0,
+            sblock: size_of_val(&*self.chunk_rank) + size_of_val(&*self.chunks),
+        };
+        for chunk in &self.chunks {
+            let s = chunk.space_breakdown();
+            space.words += s.words;
+            space.block += s.block;
+            space.sblock += s.sblock;
+        }
+        space
+    }
+}
+
+impl FromIterator<bool> for HybridBitvector {
+    fn from_iter<T: IntoIterator<Item = bool>>(iter: T) -> Self {
+        let mut len = 0;
+        let mut chunk_rank = vec![0];
+        let mut chunks = Vec::new();
+        let mut buf = Vec::with_capacity(CHUNK);
+        let mut iter = iter.into_iter().peekable();
+        while iter.peek().is_some() {
+            buf.clear();
+            buf.extend(iter.by_ref().take(CHUNK));
+            len += buf.len();
+            let ones = buf.iter().filter(|&&b| b).count() as u64;
+            chunk_rank.push(chunk_rank.last().unwrap() + ones);
+            chunks.push(Chunk::new(&buf));
+        }
+        Self {
+            len,
+            chunk_rank,
+            chunks,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{test_rank_exhaustive, test_rank_implementation};
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    #[test]
+    fn test_hybrid() {
+        test_rank_implementation::<HybridBitvector>();
+    }
+
+    #[test]
+    fn test_hybrid_exhaustive() {
+        test_rank_exhaustive::<HybridBitvector>();
+    }
+
+    #[test]
+    fn test_hybrid_mixed() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut a = Vec::new();
+        for _ in 0..30 {
+            let len = rng.random_range(1..3 * CHUNK);
+            match rng.random_range(0..4) {
+                0 => a.extend((0..len).map(|_| rng.random_ratio(1, 2))),
+                1 => a.extend((0..len).map(|_| rng.random_ratio(1, 1000))),
+                2 => a.extend((0..len).map(|i| i / 3000 % 2 == 0)),
+                _ => a.extend(core::iter::repeat_n(rng.random_ratio(1, 2), len)),
+            }
+        }
+        let bvec: HybridBitvector = a.iter().copied().collect();
+        let (dense, sparse, runs) = bvec.encoding_counts();
+        assert!(dense > 0 && sparse > 0 && runs > 0);
+
+        let ones: Vec<usize> = (0..a.len()).filter(|&i| a[i]).collect();
+        for _ in 0..100_000 {
+            let index = rng.random_range(0..=a.len());
+            assert_eq!(bvec.rank(index), ones.partition_point(|&i| i < index));
+            let k = rng.random_range(0..ones.len());
+            assert_eq!(bvec.select(k), ones[k]);
+        }
+        assert_eq!(bvec.rank(a.len()), ones.len());
+    }
+}
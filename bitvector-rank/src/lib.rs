@@ -1,15 +1,64 @@
+#![no_std]
+
+extern crate alloc;
+#[cfg(any(feature = "std", test))]
+extern crate std;
+
+mod counter;
+mod hybrid;
 mod rank_1;
 mod rank_256_64;
 mod rank_256_64_interlaced;
 mod rank_512_64_interlaced;
 mod rank_64;
 mod rank_64_interlaced;
+mod succinct_rmq;
 
+#[cfg(any(feature = "std", test))]
 pub mod test_utils;
 
+pub use counter::{Counter, CounterOverflow};
+pub use hybrid::HybridBitvector;
 pub use rank_1::Rank1;
 pub use rank_64::Rank64;
 pub use rank_64_interlaced::Rank64Interlaced;
 pub use rank_256_64::Rank25664;
 pub use rank_256_64_interlaced::Rank25664Interlaced;
 pub use rank_512_64_interlaced::Rank51264Interlaced;
+pub use succinct_rmq::SuccinctRmq;
+
+pub trait RankDataStructure: FromIterator<bool> {
+    fn rank(&self, index: usize) -> usize;
+    fn space_breakdown(&self) -> SpaceBreakdown;
+}
+
+/// Heap bytes used by each part of a rank layout. Interlaced layouts count their header
+/// words as counters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpaceBreakdown {
+    /// Raw bitvector words.
+    pub words: usize,
+    /// Counters for each $64$-bit word.
+    pub block: usize,
+    /// Counters for each superblock.
+    pub sblock: usize,
+}
+
+impl SpaceBreakdown {
+    pub fn total(&self) -> usize {
+        self.words + self.block + self.sblock
+    }
+
+    /// Bits used per input bit for a vector of length `len`.
+    pub fn bits_per_bit(&self, len: usize) -> f64 {
+        (self.total() * 8) as f64 / len as f64
+    }
+}
+
+/// Returns the position of the `k`-th set bit of `word`.
+pub(crate) fn select_in_word(mut word: u64, k: usize) -> usize {
+    for _ in 0..k {
+        word &= word - 1;
+    }
+    word.trailing_zeros() as usize
+}
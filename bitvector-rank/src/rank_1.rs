@@ -1,3 +1,5 @@
+use alloc::{vec, vec::Vec};
+
 pub struct Rank1 {
     sum: Vec<u64>,
 }
@@ -7,10 +9,18 @@ impl Rank1 {
     }
 }
 
-impl crate::test_utils::RankDataStructure for Rank1 {
+impl crate::RankDataStructure for Rank1 {
     fn rank(&self, index: usize) -> usize {
         self.rank(index)
     }
+
+    fn space_breakdown(&self) -> crate::SpaceBreakdown {
+        crate::SpaceBreakdown {
+            words: 0,
+            block: size_of_val(&*self.sum),
+            sblock: 0,
+        }
+    }
 }
 
 impl FromIterator<bool> for Rank1 {
@@ -29,10 +39,15 @@ impl FromIterator<bool> for Rank1 {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::test_utils::test_rank_implementation;
+    use crate::test_utils::{test_rank_exhaustive, test_rank_implementation};
 
     #[test]
     fn test_rank1() {
         test_rank_implementation::<Rank1>();
     }
+
+    #[test]
+    fn test_rank1_exhaustive() {
+        test_rank_exhaustive::<Rank1>();
+    }
 }
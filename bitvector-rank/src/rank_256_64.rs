@@ -1,32 +1,32 @@
-pub struct Rank25664 {
+use crate::counter::{Counter, CounterOverflow, to_counter};
+use alloc::{vec, vec::Vec};
+
+/// `C = u32` halves `sblock` for vectors with fewer than 2^32 ones.
+pub struct Rank25664<C = u64> {
     len: usize,
     words: Vec<u64>,
     block: Vec<u8>,
-    sblock: Vec<u64>,
+    sblock: Vec<C>,
 }
-impl Rank25664 {
+impl<C: Counter> Rank25664<C> {
     pub fn rank(&self, index: usize) -> usize {
         assert!(index <= self.len);
-        let ans = self.sblock[index / 256]
+        let ans = self.sblock[index / 256].to_u64()
             + u64::from(self.block[index / 64])
             + u64::from((self.words[index / 64] & ((1 << (index % 64)) - 1)).count_ones());
         ans as usize
     }
-}
 
-impl crate::test_utils::RankDataStructure for Rank25664 {
-    fn rank(&self, index: usize) -> usize {
-        self.rank(index)
+    pub(crate) fn words(&self) -> &[u64] {
+        &self.words
     }
-}
 
-impl FromIterator<bool> for Rank25664 {
-    fn from_iter<T: IntoIterator<Item = bool>>(iter: T) -> Self {
+    pub fn try_from_iter<T: IntoIterator<Item = bool>>(iter: T) -> Result<Self, CounterOverflow> {
         let mut len = 0;
         let mut word = 0u64;
         let mut words = vec![];
         let mut block = vec![0];
-        let mut sblock = vec![0];
+        let mut sblock = vec![to_counter(0)?];
         let mut sum = 0u8;
         let mut ssum = 0u64;
         for b in iter {
@@ -34,33 +34,101 @@ impl FromIterator<bool> for Rank25664 {
             len += 1;
             if len % 64 == 0 {
                 let pc = word.count_ones();
-                words.push(std::mem::take(&mut word));
-                sum += pc as u8;
+                words.push(core::mem::take(&mut word));
                 ssum += u64::from(pc);
                 if len % 256 == 0 {
-                    sblock.push(ssum);
+                    sblock.push(to_counter(ssum)?);
                     sum = 0;
+                } else {
+                    sum += pc as u8;
                 }
                 block.push(sum);
             }
         }
         words.push(word);
-        Self {
+        Ok(Self {
             len,
             words,
             block,
             sblock,
+        })
+    }
+}
+
+impl<C: Counter> crate::RankDataStructure for Rank25664<C> {
+    fn rank(&self, index: usize) -> usize {
+        self.rank(index)
+    }
+
+    fn space_breakdown(&self) -> crate::SpaceBreakdown {
+        crate::SpaceBreakdown {
+            words: size_of_val(&*self.words),
+            block: size_of_val(&*self.block),
+            sblock: size_of_val(&*self.sblock),
         }
     }
 }
 
+/// Panics if a count overflows `C`; use [`Rank25664::try_from_iter`] to handle it.
+impl<C: Counter> FromIterator<bool> for Rank25664<C> {
+    fn from_iter<T: IntoIterator<Item = bool>>(iter: T) -> Self {
+        Self::try_from_iter(iter).unwrap()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::test_utils::test_rank_implementation;
+    use crate::test_utils::{test_rank_exhaustive, test_rank_implementation};
 
     #[test]
     fn test_rank25664() {
         test_rank_implementation::<Rank25664>();
     }
+
+    #[test]
+    fn test_rank25664_exhaustive() {
+        test_rank_exhaustive::<Rank25664>();
+    }
+
+    #[test]
+    fn test_rank25664_u32() {
+        test_rank_implementation::<Rank25664<u32>>();
+    }
+
+    #[test]
+    fn test_rank25664_u32_exhaustive() {
+        test_rank_exhaustive::<Rank25664<u32>>();
+    }
+
+    #[test]
+    fn test_rank25664_space_breakdown() {
+        let bvec: Rank25664 = std::iter::repeat_n(true, 256 * 1000).collect();
+        let space = crate::RankDataStructure::space_breakdown(&bvec);
+        assert_eq!(space.words, 8 * 4001);
+        assert_eq!(space.block, 4001);
+        assert_eq!(space.sblock, 8 * 1001);
+        let bvec: Rank25664<u32> = std::iter::repeat_n(true, 256 * 1000).collect();
+        let space = crate::RankDataStructure::space_breakdown(&bvec);
+        assert_eq!(space.sblock, 4 * 1001);
+    }
+
+    /// A full 256-bit block of ones used to overflow the `u8` in-block sum before it was
+    /// reset, which panicked in debug builds.
+    #[test]
+    fn test_rank25664_full_blocks() {
+        let bvec: Rank25664 = std::iter::repeat_n(true, 256 * 4 + 1).collect();
+        for i in 0..=256 * 4 + 1 {
+            assert_eq!(bvec.rank(i), i);
+        }
+    }
+
+    #[test]
+    fn test_rank25664_overflow() {
+        assert!(Rank25664::<u8>::try_from_iter(std::iter::repeat_n(true, 255)).is_ok());
+        assert_eq!(
+            Rank25664::<u8>::try_from_iter(std::iter::repeat_n(true, 256)).err(),
+            Some(CounterOverflow { count: 256 }),
+        );
+    }
 }
@@ -1,3 +1,5 @@
+use alloc::{vec, vec::Vec};
+
 pub struct Rank25664Interlaced {
     len: usize,
     words: Vec<u64>,
@@ -16,10 +18,19 @@ impl Rank25664Interlaced {
     }
 }
 
-impl crate::test_utils::RankDataStructure for Rank25664Interlaced {
+impl crate::RankDataStructure for Rank25664Interlaced {
     fn rank(&self, index: usize) -> usize {
         self.rank(index)
     }
+
+    fn space_breakdown(&self) -> crate::SpaceBreakdown {
+        let sblocks = self.words.len().div_ceil(6);
+        crate::SpaceBreakdown {
+            words: (self.words.len() - sblocks * 2) * 8,
+            block: sblocks * 8,
+            sblock: sblocks * 8,
+        }
+    }
 }
 
 impl FromIterator<bool> for Rank25664Interlaced {
@@ -32,7 +43,7 @@ impl FromIterator<bool> for Rank25664Interlaced {
             word |= u64::from(elm) << (len % 64);
             if (len + 1).is_multiple_of(64) {
                 lsum += word.count_ones();
-                words.push(std::mem::take(&mut word));
+                words.push(core::mem::take(&mut word));
                 let a = len / 256;
                 let b = len % 256 / 64;
                 if (len + 1).is_multiple_of(256) {
@@ -53,10 +64,15 @@ impl FromIterator<bool> for Rank25664Interlaced {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::test_utils::test_rank_implementation;
+    use crate::test_utils::{test_rank_exhaustive, test_rank_implementation};
 
     #[test]
     fn test_rank25664_interlaced() {
         test_rank_implementation::<Rank25664Interlaced>();
     }
+
+    #[test]
+    fn test_rank25664_interlaced_exhaustive() {
+        test_rank_exhaustive::<Rank25664Interlaced>();
+    }
 }
@@ -1,3 +1,5 @@
+use alloc::{vec, vec::Vec};
+
 pub struct Rank51264Interlaced {
     len: usize,
     words: Vec<u64>,
@@ -19,12 +21,139 @@ impl Rank51264Interlaced {
             } + u64::from((word & ((1 << c) - 1)).count_ones());
         ans as usize
     }
+
+    /// Returns the position of the `k`-th one (0-indexed).
+    pub fn select(&self, k: usize) -> usize {
+        let k = k as u64;
+        let (mut a, mut hi) = (0, self.words.len().div_ceil(10));
+        while hi - a > 1 {
+            let mid = a.midpoint(hi);
+            if self.words[mid * 10] <= k {
+                a = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        let packed = self.words[a * 10 + 1];
+        let inner = |b: usize| {
+            if b == 0 {
+                0
+            } else {
+                packed >> ((b - 1) * 9) & 511
+            }
+        };
+        let blocks = (self.words.len() - a * 10 - 2).min(8);
+        let b = (1..blocks)
+            .take_while(|&b| self.words[a * 10] + inner(b) <= k)
+            .last()
+            .unwrap_or(0);
+        let rest = k - self.words[a * 10] - inner(b);
+        let word = self.words[a * 10 + 2 + b];
+        assert!(rest < u64::from(word.count_ones()), "select out of range");
+        a * 512 + b * 64 + crate::select_in_word(word, rest as usize)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Rank51264Interlaced {
+    /// Builds the same layout as [`FromIterator`] from packed words, splitting the
+    /// superblocks across all available threads.
+    ///
+    /// Bit `i` of the input is `words[i / 64] >> (i % 64) & 1`. Bits at or beyond `len`
+    /// are ignored.
+    pub fn par_from_words(words: &[u64], len: usize) -> Self {
+        let threads = std::thread::available_parallelism().map_or(1, core::num::NonZero::get);
+        Self::par_from_words_with_threads(words, len, threads)
+    }
+
+    /// [`Self::par_from_words`] on a single thread, the baseline for its speedup.
+    pub fn from_words(words: &[u64], len: usize) -> Self {
+        Self::par_from_words_with_threads(words, len, 1)
+    }
+
+    fn par_from_words_with_threads(words: &[u64], len: usize, threads: usize) -> Self {
+        assert!(len <= words.len() * 64);
+        let full = len / 64;
+        let nsb = full / 8;
+        let rem = full % 8;
+        let mut out = vec![0u64; nsb * 10 + 2 + rem + 1];
+        let (body, tail) = out.split_at_mut(nsb * 10);
+
+        // First pass: lay out each chunk with sums local to the chunk.
+        let chunk = nsb.div_ceil(threads.max(1)).max(1);
+        let totals: Vec<u64> = std::thread::scope(|s| {
+            let handles: Vec<_> = body
+                .chunks_mut(chunk * 10)
+                .zip(words[..nsb * 8].chunks(chunk * 8))
+                .map(|(out, src)| s.spawn(move || layout_superblocks(out, src)))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        // Second pass: add the prefix sums of the chunk totals.
+        let mut offsets = Vec::with_capacity(totals.len());
+        let mut sum = 0u64;
+        for total in totals {
+            offsets.push(sum);
+            sum += total;
+        }
+        std::thread::scope(|s| {
+            for (out, offset) in body.chunks_mut(chunk * 10).zip(offsets) {
+                s.spawn(move || {
+                    for sblock in out.chunks_mut(10) {
+                        sblock[0] += offset;
+                    }
+                });
+            }
+        });
+
+        tail[0] = sum;
+        let mut lsum = 0u32;
+        for (b, &word) in words[nsb * 8..full].iter().enumerate() {
+            lsum += word.count_ones();
+            tail[1] |= u64::from(lsum) << (b * 9);
+            tail[2 + b] = word;
+        }
+        if !len.is_multiple_of(64) {
+            tail[2 + rem] = words[full] & ((1 << (len % 64)) - 1);
+        }
+        Self { len, words: out }
+    }
+}
+
+/// Lays out whole superblocks of `src` into `out` and returns their popcount. The
+/// global sums are relative to the start of `src`.
+#[cfg(feature = "std")]
+fn layout_superblocks(out: &mut [u64], src: &[u64]) -> u64 {
+    let mut sum = 0u64;
+    for (out, src) in out.chunks_mut(10).zip(src.chunks(8)) {
+        out[0] = sum;
+        let mut lsum = 0u32;
+        for (b, &word) in src.iter().enumerate() {
+            lsum += word.count_ones();
+            if b < 7 {
+                out[1] |= u64::from(lsum) << (b * 9);
+            }
+            out[2 + b] = word;
+        }
+        sum += u64::from(lsum);
+    }
+    sum
 }
 
-impl crate::test_utils::RankDataStructure for Rank51264Interlaced {
+impl crate::RankDataStructure for Rank51264Interlaced {
     fn rank(&self, index: usize) -> usize {
         self.rank(index)
     }
+
+    fn space_breakdown(&self) -> crate::SpaceBreakdown {
+        let sblocks = self.words.len().div_ceil(10);
+        crate::SpaceBreakdown {
+            words: (self.words.len() - sblocks * 2) * 8,
+            block: sblocks * 8,
+            sblock: sblocks * 8,
+        }
+    }
 }
 
 impl FromIterator<bool> for Rank51264Interlaced {
@@ -37,7 +166,7 @@ impl FromIterator<bool> for Rank51264Interlaced {
             word |= u64::from(elm) << (len % 64);
             if (len + 1).is_multiple_of(64) {
                 lsum += word.count_ones();
-                words.push(std::mem::take(&mut word));
+                words.push(core::mem::take(&mut word));
                 let a = len / 512;
                 let b = len % 512 / 64;
                 if (len + 1).is_multiple_of(512) {
@@ -58,10 +187,68 @@ impl FromIterator<bool> for Rank51264Interlaced {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::test_utils::test_rank_implementation;
+    use crate::test_utils::{test_rank_exhaustive, test_rank_implementation};
 
     #[test]
     fn test_rank51264_interlaced() {
         test_rank_implementation::<Rank51264Interlaced>();
     }
+
+    #[test]
+    fn test_rank51264_interlaced_exhaustive() {
+        test_rank_exhaustive::<Rank51264Interlaced>();
+    }
+
+    #[test]
+    fn test_rank51264_interlaced_select() {
+        for &(_, pattern) in crate::test_utils::PATTERNS {
+            for n in [0, 1, 63, 64, 511, 512, 513, 1600] {
+                let a: Vec<bool> = (0..n).map(pattern).collect();
+                let bvec: Rank51264Interlaced = a.iter().copied().collect();
+                let ones = (0..n).filter(|&i| a[i]);
+                for (k, i) in ones.enumerate() {
+                    assert_eq!(bvec.select(k), i);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_rank51264_interlaced_space_breakdown() {
+        for n in [0, 1, 511, 512, 512 * 1000 + 100] {
+            let bvec: Rank51264Interlaced = std::iter::repeat_n(true, n).collect();
+            let space = crate::RankDataStructure::space_breakdown(&bvec);
+            assert_eq!(space.words, 8 * (n / 64 + 1));
+            assert_eq!(space.block, 8 * (n / 512 + 1));
+            assert_eq!(space.sblock, 8 * (n / 512 + 1));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_par_from_words() {
+        use rand::{Rng, SeedableRng, rngs::StdRng};
+
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..100 {
+            let n = rng.random_range(0..=20_000);
+            let a: Vec<bool> = std::iter::repeat_with(|| rng.random_ratio(1, 2))
+                .take(n)
+                .collect();
+            let mut words = vec![0u64; n.div_ceil(64)];
+            for (i, &b) in a.iter().enumerate() {
+                words[i / 64] |= u64::from(b) << (i % 64);
+            }
+            // Garbage past `len` must be ignored.
+            if n % 64 != 0 {
+                *words.last_mut().unwrap() |= !0 << (n % 64);
+            }
+            let expected: Rank51264Interlaced = a.iter().copied().collect();
+            for threads in [1, 2, 3, 8] {
+                let result = Rank51264Interlaced::par_from_words_with_threads(&words, n, threads);
+                assert_eq!(result.len, expected.len);
+                assert_eq!(result.words, expected.words);
+            }
+        }
+    }
 }
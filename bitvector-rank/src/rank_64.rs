@@ -1,52 +1,93 @@
-pub struct Rank64 {
+use crate::counter::{Counter, CounterOverflow, to_counter};
+use alloc::{vec, vec::Vec};
+
+/// `C = u32` halves the directory for vectors with fewer than 2^32 ones.
+pub struct Rank64<C = u64> {
     len: usize,
     words: Vec<u64>,
-    block: Vec<u64>,
+    block: Vec<C>,
 }
-impl Rank64 {
+impl<C: Counter> Rank64<C> {
     pub fn rank(&self, index: usize) -> usize {
         assert!(index <= self.len);
-        let ans = self.block[index / 64]
+        let ans = self.block[index / 64].to_u64()
             + u64::from((self.words[index / 64] & ((1 << (index % 64)) - 1)).count_ones());
         ans as usize
     }
-}
-
-impl crate::test_utils::RankDataStructure for Rank64 {
-    fn rank(&self, index: usize) -> usize {
-        self.rank(index)
-    }
-}
 
-impl FromIterator<bool> for Rank64 {
-    fn from_iter<T: IntoIterator<Item = bool>>(iter: T) -> Self {
+    pub fn try_from_iter<T: IntoIterator<Item = bool>>(iter: T) -> Result<Self, CounterOverflow> {
         let mut len = 0;
         let mut word = 0u64;
         let mut words = vec![];
-        let mut block = vec![0];
+        let mut block = vec![to_counter(0)?];
         let mut sum = 0u64;
         for b in iter {
             word |= u64::from(b) << (len % 64);
             len += 1;
             if len % 64 == 0 {
                 let pc = word.count_ones();
-                words.push(std::mem::take(&mut word));
+                words.push(core::mem::take(&mut word));
                 sum += u64::from(pc);
-                block.push(sum);
+                block.push(to_counter(sum)?);
             }
         }
         words.push(word);
-        Self { len, words, block }
+        Ok(Self { len, words, block })
+    }
+}
+
+impl<C: Counter> crate::RankDataStructure for Rank64<C> {
+    fn rank(&self, index: usize) -> usize {
+        self.rank(index)
+    }
+
+    fn space_breakdown(&self) -> crate::SpaceBreakdown {
+        crate::SpaceBreakdown {
+            words: size_of_val(&*self.words),
+            block: size_of_val(&*self.block),
+            sblock: 0,
+        }
+    }
+}
+
+/// Panics if a count overflows `C`; use [`Rank64::try_from_iter`] to handle it.
+impl<C: Counter> FromIterator<bool> for Rank64<C> {
+    fn from_iter<T: IntoIterator<Item = bool>>(iter: T) -> Self {
+        Self::try_from_iter(iter).unwrap()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::test_utils::test_rank_implementation;
+    use crate::test_utils::{test_rank_exhaustive, test_rank_implementation};
 
     #[test]
     fn test_rank64() {
         test_rank_implementation::<Rank64>();
     }
+
+    #[test]
+    fn test_rank64_exhaustive() {
+        test_rank_exhaustive::<Rank64>();
+    }
+
+    #[test]
+    fn test_rank64_u32() {
+        test_rank_implementation::<Rank64<u32>>();
+    }
+
+    #[test]
+    fn test_rank64_u32_exhaustive() {
+        test_rank_exhaustive::<Rank64<u32>>();
+    }
+
+    #[test]
+    fn test_rank64_overflow() {
+        assert!(Rank64::<u8>::try_from_iter(std::iter::repeat_n(true, 255)).is_ok());
+        assert_eq!(
+            Rank64::<u8>::try_from_iter(std::iter::repeat_n(true, 256)).err(),
+            Some(CounterOverflow { count: 256 }),
+        );
+    }
 }
@@ -1,3 +1,5 @@
+use alloc::{vec, vec::Vec};
+
 pub struct Rank64Interlaced {
     len: usize,
     words: Vec<u64>,
@@ -14,10 +16,19 @@ impl Rank64Interlaced {
     }
 }
 
-impl crate::test_utils::RankDataStructure for Rank64Interlaced {
+impl crate::RankDataStructure for Rank64Interlaced {
     fn rank(&self, index: usize) -> usize {
         self.rank(index)
     }
+
+    fn space_breakdown(&self) -> crate::SpaceBreakdown {
+        let sums = self.words.len() / 2;
+        crate::SpaceBreakdown {
+            words: (self.words.len() - sums) * 8,
+            block: sums * 8,
+            sblock: 0,
+        }
+    }
 }
 
 impl FromIterator<bool> for Rank64Interlaced {
@@ -31,7 +42,7 @@ impl FromIterator<bool> for Rank64Interlaced {
             len += 1;
             if len.is_multiple_of(64) {
                 sum += u64::from(word.count_ones());
-                words.push(std::mem::take(&mut word));
+                words.push(core::mem::take(&mut word));
                 words.push(sum);
             }
         }
@@ -43,10 +54,15 @@ impl FromIterator<bool> for Rank64Interlaced {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::test_utils::test_rank_implementation;
+    use crate::test_utils::{test_rank_exhaustive, test_rank_implementation};
 
     #[test]
     fn test_rank25664_interlaced() {
         test_rank_implementation::<Rank64Interlaced>();
     }
+
+    #[test]
+    fn test_rank25664_interlaced_exhaustive() {
+        test_rank_exhaustive::<Rank64Interlaced>();
+    }
 }
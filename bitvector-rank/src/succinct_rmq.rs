@@ -0,0 +1,455 @@
+use crate::Rank25664;
+use alloc::{boxed::Box, vec, vec::Vec};
+use core::ops::Range;
+
+/// Words per block.
+const BLOCK: usize = 8;
+/// Blocks per superblock.
+const SUPER: usize = 64;
+const SUPER_LOG: usize = 6;
+/// Opens per select group.
+const SELECT_SAMPLE: usize = 256;
+/// Select groups spanning more bits than this store every position explicitly.
+const SELECT_SPARSE: usize = 1 << 16;
+
+/// Range-minimum queries over an array in $2n$ bits plus a directory, without keeping the
+/// array itself.
+///
+/// The array is encoded as balanced parentheses: element `i` pops every greater element
+/// off a stack (one `)` each) and then pushes itself (one `(`). With `E(p)` the excess of
+/// the first `p` bits and `o_i` the position of the `(` of `i`, the leftmost minimum of
+/// `a[i..=j]` is the element whose `(` sits at the rightmost minimum of `E` on
+/// `[o_i, o_j]`.
+///
+/// `argmin` takes a constant number of table lookups apart from the two `select`s, which take
+/// O(log `SELECT_SPARSE`) time each: up to 12 `rank`s.
+pub struct SuccinctRmq {
+    len: usize,
+    bp: Rank25664,
+    /// Per word, how far `E` drops below its value at the word start.
+    word_min: Vec<u8>,
+    /// Per block, how far `E` drops below its value at the block start.
+    block_min: Vec<u16>,
+    /// Per superblock, how far `E` drops below its value at the superblock start.
+    super_min: Vec<u16>,
+    /// Per superblock, sparse tables of the rightmost minimum block for levels
+    /// `1..=SUPER_LOG`, indexed by `(s * SUPER_LOG + k - 1) * SUPER + i`.
+    block_table: Vec<u8>,
+    /// Sparse tables of the rightmost minimum superblock for levels `1..`.
+    super_table: Vec<Vec<u32>>,
+    select: Vec<SelectGroup>,
+}
+
+enum SelectGroup {
+    /// Position of the first `(`; the rest are found by binary search on rank.
+    Dense(u64),
+    /// Position of every `(` in the group.
+    Sparse(Box<[u64]>),
+}
+
+impl SelectGroup {
+    fn start(&self) -> usize {
+        match self {
+            SelectGroup::Dense(start) => *start as usize,
+            SelectGroup::Sparse(pos) => pos[0] as usize,
+        }
+    }
+}
+
+/// A range whose minimum is known but whose position is resolved only if it wins.
+#[derive(Clone, Copy)]
+enum Piece {
+    Pos(usize),
+    Word(usize),
+    Block(usize),
+    Super(usize),
+}
+
+impl SuccinctRmq {
+    pub fn new<T: Ord>(a: &[T]) -> Self {
+        let len = a.len();
+        let mut bits = Vec::with_capacity(2 * len);
+        let mut stack: Vec<&T> = vec![];
+        for x in a {
+            while stack.last().is_some_and(|&y| y > x) {
+                stack.pop();
+                bits.push(false);
+            }
+            stack.push(x);
+            bits.push(true);
+        }
+        bits.extend(core::iter::repeat_n(false, stack.len()));
+        let bp: Rank25664 = bits.iter().copied().collect();
+
+        let nwords = bits.len().div_ceil(64);
+        let nblocks = nwords.div_ceil(BLOCK);
+        let nsupers = nblocks.div_ceil(SUPER);
+        let mut word_min = vec![0u8; nwords];
+        let mut block_min = vec![0u16; nblocks];
+        let mut super_min = vec![0u16; nsupers];
+        let mut block_value = vec![0usize; nblocks];
+        let mut super_value = vec![0usize; nsupers];
+        let mut opens = vec![];
+        let (mut e, mut we, mut be, mut se) = (0usize, 0, 0, 0);
+        for (p, &b) in bits.iter().enumerate() {
+            let (w, bl, s) = (p / 64, p / (64 * BLOCK), p / (64 * BLOCK * SUPER));
+            if p % 64 == 0 {
+                we = e;
+            }
+            if p % (64 * BLOCK) == 0 {
+                be = e;
+                block_value[bl] = e;
+            }
+            if p % (64 * BLOCK * SUPER) == 0 {
+                se = e;
+                super_value[s] = e;
+            }
+            word_min[w] = word_min[w].max((we - e.min(we)) as u8);
+            block_min[bl] = block_min[bl].max((be - e.min(be)) as u16);
+            super_min[s] = super_min[s].max((se - e.min(se)) as u16);
+            block_value[bl] = block_value[bl].min(e);
+            super_value[s] = super_value[s].min(e);
+            if b {
+                opens.push(p);
+                e += 1;
+            } else {
+                e -= 1;
+            }
+        }
+
+        Self {
+            len,
+            bp,
+            word_min,
+            block_min,
+            super_min,
+            block_table: build_block_table(&block_value),
+            super_table: build_super_table(&super_value),
+            select: build_select(&opens, bits.len()),
+        }
+    }
+
+    /// Returns the index of the leftmost minimum of `a[range]`.
+    pub fn argmin(&self, range: Range<usize>) -> usize {
+        let Range { start, end } = range;
+        assert!(start < end && end <= self.len);
+        let x = self.select(start);
+        let y = self.select(end - 1);
+        let (wx, wy) = (x / 64, y / 64);
+        let best = if wx == wy {
+            self.scan_word(wx, x % 64, y % 64)
+        } else {
+            let mut best = self.scan_word(wx, x % 64, 63);
+            if wx + 1 < wy {
+                best = better(best, self.words_min(wx + 1, wy - 1));
+            }
+            better(best, self.scan_word(wy, 0, y % 64))
+        };
+        self.bp.rank(self.resolve(best.1))
+    }
+
+    /// Position of the `k`-th `(`.
+    ///
+    /// A sparse group is a lookup. A dense group spans at most `SELECT_SPARSE` bits, so the
+    /// binary search runs over at most `SELECT_SPARSE / 64 + 1` words and calls `rank` at
+    /// most 12 times.
+    fn select(&self, k: usize) -> usize {
+        let g = k / SELECT_SAMPLE;
+        match &self.select[g] {
+            SelectGroup::Sparse(pos) => pos[k % SELECT_SAMPLE] as usize,
+            &SelectGroup::Dense(start) => {
+                let end = self
+                    .select
+                    .get(g + 1)
+                    .map_or(2 * self.len, SelectGroup::start);
+                let (mut lo, mut hi) = (start as usize / 64, end.div_ceil(64));
+                while hi - lo > 1 {
+                    let mid = lo.midpoint(hi);
+                    if self.bp.rank(64 * mid) <= k {
+                        lo = mid;
+                    } else {
+                        hi = mid;
+                    }
+                }
+                64 * lo + crate::select_in_word(self.bp.words()[lo], k - self.bp.rank(64 * lo))
+            }
+        }
+    }
+
+    fn excess(&self, p: usize) -> usize {
+        2 * self.bp.rank(p) - p
+    }
+
+    /// Rightmost minimum of `E` over offsets `lo..=hi` of word `w`.
+    fn scan_word(&self, w: usize, lo: usize, hi: usize) -> (usize, Piece) {
+        let word = self.bp.words()[w];
+        let mut e = self.excess(64 * w + lo);
+        let mut best = (e, lo);
+        for t in lo + 1..=hi {
+            if word >> (t - 1) & 1 == 1 {
+                e += 1;
+            } else {
+                e -= 1;
+            }
+            if e <= best.0 {
+                best = (e, t);
+            }
+        }
+        (best.0, Piece::Pos(64 * w + best.1))
+    }
+
+    fn word_value(&self, w: usize) -> usize {
+        self.excess(64 * w) - usize::from(self.word_min[w])
+    }
+
+    fn block_value(&self, b: usize) -> usize {
+        self.excess(64 * BLOCK * b) - usize::from(self.block_min[b])
+    }
+
+    fn super_value(&self, s: usize) -> usize {
+        self.excess(64 * BLOCK * SUPER * s) - usize::from(self.super_min[s])
+    }
+
+    /// Rightmost minimum over whole words `a..=b`.
+    fn words_min(&self, a: usize, b: usize) -> (usize, Piece) {
+        let (ba, bb) = (a / BLOCK, b / BLOCK);
+        if ba == bb {
+            return self.scan_words(a, b);
+        }
+        let mut best = self.scan_words(a, ba * BLOCK + BLOCK - 1);
+        if ba + 1 < bb {
+            best = better(best, self.blocks_min(ba + 1, bb - 1));
+        }
+        better(best, self.scan_words(bb * BLOCK, b))
+    }
+
+    fn scan_words(&self, a: usize, b: usize) -> (usize, Piece) {
+        (a..=b)
+            .map(|w| (self.word_value(w), Piece::Word(w)))
+            .reduce(better)
+            .unwrap()
+    }
+
+    /// Rightmost minimum over whole blocks `a..=b`.
+    fn blocks_min(&self, a: usize, b: usize) -> (usize, Piece) {
+        let (sa, sb) = (a / SUPER, b / SUPER);
+        let piece = |s, l, r| {
+            let b = s * SUPER + self.block_in_super(s, l, r);
+            (self.block_value(b), Piece::Block(b))
+        };
+        if sa == sb {
+            return piece(sa, a % SUPER, b % SUPER);
+        }
+        let mut best = piece(sa, a % SUPER, SUPER - 1);
+        if sa + 1 < sb {
+            let s = self.supers_min(sa + 1, sb - 1);
+            best = better(best, (self.super_value(s), Piece::Super(s)));
+        }
+        better(best, piece(sb, 0, b % SUPER))
+    }
+
+    /// Rightmost minimum block among blocks `l..=r` of superblock `s`, relative to `s`.
+    fn block_in_super(&self, s: usize, l: usize, r: usize) -> usize {
+        let k = (r - l + 1).ilog2() as usize;
+        if k == 0 {
+            return l;
+        }
+        let table = &self.block_table[(s * SUPER_LOG + k - 1) * SUPER..];
+        let (left, right) = (table[l] as usize, table[r + 1 - (1 << k)] as usize);
+        if self.block_value(s * SUPER + right) <= self.block_value(s * SUPER + left) {
+            right
+        } else {
+            left
+        }
+    }
+
+    /// Rightmost minimum superblock among `a..=b`.
+    fn supers_min(&self, a: usize, b: usize) -> usize {
+        let k = (b - a + 1).ilog2() as usize;
+        if k == 0 {
+            return a;
+        }
+        let table = &self.super_table[k - 1];
+        let (left, right) = (table[a] as usize, table[b + 1 - (1 << k)] as usize);
+        if self.super_value(right) <= self.super_value(left) {
+            right
+        } else {
+            left
+        }
+    }
+
+    fn resolve(&self, piece: Piece) -> usize {
+        match piece {
+            Piece::Pos(p) => p,
+            Piece::Word(w) => self.resolve(self.scan_word(w, 0, 63).1),
+            Piece::Block(b) => self.resolve(self.scan_words(b * BLOCK, b * BLOCK + BLOCK - 1).1),
+            Piece::Super(s) => {
+                let b = s * SUPER + self.block_in_super(s, 0, SUPER - 1);
+                self.resolve(Piece::Block(b))
+            }
+        }
+    }
+}
+
+fn build_block_table(block_value: &[usize]) -> Vec<u8> {
+    let nblocks = block_value.len();
+    let nsupers = nblocks.div_ceil(SUPER);
+    let mut block_table = vec![0u8; nsupers * SUPER_LOG * SUPER];
+    for s in 0..nsupers {
+        let count = SUPER.min(nblocks - s * SUPER);
+        let values = &block_value[s * SUPER..s * SUPER + count];
+        let table = &mut block_table[s * SUPER_LOG * SUPER..(s + 1) * SUPER_LOG * SUPER];
+        for k in 1..=SUPER_LOG {
+            let half = 1 << (k - 1);
+            for i in 0..count {
+                let left = if k == 1 {
+                    i
+                } else {
+                    table[(k - 2) * SUPER + i] as usize
+                };
+                let j = (i + half).min(count - 1);
+                let right = if k == 1 {
+                    j
+                } else {
+                    table[(k - 2) * SUPER + j] as usize
+                };
+                table[(k - 1) * SUPER + i] = rightmost_min(values, left, right) as u8;
+            }
+        }
+    }
+    block_table
+}
+
+fn build_super_table(super_value: &[usize]) -> Vec<Vec<u32>> {
+    let nsupers = super_value.len();
+    let mut super_table: Vec<Vec<u32>> = vec![];
+    for k in 1..usize::BITS - nsupers.leading_zeros() {
+        let half = 1 << (k - 1);
+        let prev = super_table.last();
+        let at = |i: usize| prev.map_or(i, |prev| prev[i] as usize);
+        let row = (0..=nsupers - (1 << k))
+            .map(|i| {
+                let (left, right) = (at(i), at(i + half));
+                rightmost_min(super_value, left, right) as u32
+            })
+            .collect();
+        super_table.push(row);
+    }
+    super_table
+}
+
+/// `len` is the length of the parentheses sequence.
+fn build_select(opens: &[usize], len: usize) -> Vec<SelectGroup> {
+    opens
+        .chunks(SELECT_SAMPLE)
+        .enumerate()
+        .map(|(g, group)| {
+            let next = opens.get((g + 1) * SELECT_SAMPLE).copied().unwrap_or(len);
+            if next - group[0] > SELECT_SPARSE {
+                SelectGroup::Sparse(group.iter().map(|&p| p as u64).collect())
+            } else {
+                SelectGroup::Dense(group[0] as u64)
+            }
+        })
+        .collect()
+}
+
+fn rightmost_min(values: &[usize], left: usize, right: usize) -> usize {
+    if values[right] <= values[left] {
+        right
+    } else {
+        left
+    }
+}
+
+/// Prefers `right` on ties, so that the rightmost minimum wins.
+fn better(left: (usize, Piece), right: (usize, Piece)) -> (usize, Piece) {
+    if right.0 <= left.0 { right } else { left }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    /// Leftmost-argmin sparse table.
+    struct SparseTable<'a> {
+        a: &'a [u32],
+        table: Vec<Vec<usize>>,
+    }
+
+    impl<'a> SparseTable<'a> {
+        fn new(a: &'a [u32]) -> Self {
+            let mut table = vec![(0..a.len()).collect::<Vec<_>>()];
+            for k in 1..usize::BITS - a.len().leading_zeros() {
+                let prev = table.last().unwrap();
+                let row = (0..=a.len() - (1 << k))
+                    .map(|i| {
+                        let (l, r) = (prev[i], prev[i + (1 << (k - 1))]);
+                        if a[r] < a[l] { r } else { l }
+                    })
+                    .collect();
+                table.push(row);
+            }
+            Self { a, table }
+        }
+
+        fn argmin(&self, range: Range<usize>) -> usize {
+            let k = range.len().ilog2() as usize;
+            let (l, r) = (
+                self.table[k][range.start],
+                self.table[k][range.end - (1 << k)],
+            );
+            if self.a[r] < self.a[l] { r } else { l }
+        }
+    }
+
+    fn test_against_sparse_table(a: &[u32], rng: &mut StdRng, q: usize) {
+        let rmq = SuccinctRmq::new(a);
+        let st = SparseTable::new(a);
+        for _ in 0..q {
+            let mut l = rng.random_range(0..a.len());
+            let mut r = rng.random_range(0..a.len());
+            if l > r {
+                core::mem::swap(&mut l, &mut r);
+            }
+            assert_eq!(rmq.argmin(l..r + 1), st.argmin(l..r + 1), "{l}..{}", r + 1);
+        }
+    }
+
+    #[test]
+    fn test_succinct_rmq_small() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..200 {
+            let n = rng.random_range(1..=3000);
+            let m = rng.random_range(1..=n as u32);
+            let a: Vec<u32> = (0..n).map(|_| rng.random_range(0..m)).collect();
+            test_against_sparse_table(&a, &mut rng, 200);
+        }
+    }
+
+    #[test]
+    fn test_succinct_rmq_large() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for m in [2, 100, u32::MAX] {
+            let a: Vec<u32> = (0..200_000).map(|_| rng.random_range(0..m)).collect();
+            test_against_sparse_table(&a, &mut rng, 20_000);
+        }
+    }
+
+    #[test]
+    fn test_succinct_rmq_monotone() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let n = 100_000;
+        let inc: Vec<u32> = (0..n).collect();
+        test_against_sparse_table(&inc, &mut rng, 10_000);
+        let dec: Vec<u32> = (0..n).rev().collect();
+        test_against_sparse_table(&dec, &mut rng, 10_000);
+        // A long run of `)` makes sparse select groups.
+        let mut saw: Vec<u32> = (1..n).collect();
+        saw.extend([0, 5, 3]);
+        saw.extend(1..n);
+        saw.push(0);
+        test_against_sparse_table(&saw, &mut rng, 10_000);
+    }
+}
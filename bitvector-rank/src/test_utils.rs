@@ -1,8 +1,6 @@
+pub use crate::RankDataStructure;
 use rand::{Rng, SeedableRng, rngs::StdRng};
-
-pub trait RankDataStructure: FromIterator<bool> {
-    fn rank(&self, index: usize) -> usize;
-}
+use std::{eprintln, vec::Vec};
 
 pub fn test_rank_implementation<T: RankDataStructure>() {
     let mut rng = StdRng::seed_from_u64(42);
@@ -25,3 +23,136 @@ pub fn test_rank_implementation<T: RankDataStructure>() {
         }
     }
 }
+
+/// Every length up to this is tried by [`test_rank_exhaustive`]. It covers three 512-bit
+/// superblocks so that every directory boundary is crossed at least twice.
+pub const EXHAUSTIVE_MAX_N: usize = 1600;
+
+/// Whether bit `i` is set.
+pub type Pattern = fn(usize) -> bool;
+
+/// Fill patterns for [`test_rank_exhaustive`], chosen to hit full and empty words, blocks
+/// and superblocks.
+pub const PATTERNS: &[(&str, Pattern)] = &[
+    ("zeros", |_| false),
+    ("ones", |_| true),
+    ("alternating", |i| i % 2 == 0),
+    ("ones-except-word-ends", |i| i % 64 != 63),
+    ("word-starts", |i| i % 64 == 0),
+    ("ones-in-even-512", |i| i / 512 % 2 == 0),
+    ("ones-in-odd-256", |i| i / 256 % 2 == 1),
+    ("hashed", |i| {
+        (i as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 63 == 1
+    }),
+];
+
+/// Checks every query index of every pattern in [`PATTERNS`] for every length up to
+/// [`EXHAUSTIVE_MAX_N`]. Panics in the layout count as disagreements. The first
+/// disagreement is minimized and reported as a Rust reproducer.
+pub fn test_rank_exhaustive<T: RankDataStructure>() {
+    for &(name, pattern) in PATTERNS {
+        for n in 0..=EXHAUSTIVE_MAX_N {
+            let a: Vec<bool> = (0..n).map(pattern).collect();
+            let mut expected = Vec::with_capacity(n + 1);
+            expected.push(0);
+            for &b in &a {
+                expected.push(expected.last().unwrap() + usize::from(b));
+            }
+            let result = std::panic::catch_unwind(|| {
+                let bvec: T = a.iter().copied().collect();
+                (0..=n).find(|&index| bvec.rank(index) != expected[index])
+            });
+            let index = match result {
+                Ok(None) => continue,
+                Ok(Some(index)) => index,
+                Err(_) => (0..=n)
+                    .find(|&index| !rank_agrees::<T>(&a, index))
+                    .unwrap_or(0),
+            };
+            report_mismatch::<T>(name, a, index);
+        }
+    }
+}
+
+fn rank_agrees<T: RankDataStructure>(a: &[bool], index: usize) -> bool {
+    let expected = a[..index].iter().filter(|&&b| b).count();
+    std::panic::catch_unwind(|| {
+        let bvec: T = a.iter().copied().collect();
+        bvec.rank(index) == expected
+    })
+    .unwrap_or(false)
+}
+
+fn report_mismatch<T: RankDataStructure>(pattern: &str, mut a: Vec<bool>, index: usize) -> ! {
+    let n = a.len();
+    if !rank_agrees::<T>(&a[..index], index) {
+        a.truncate(index);
+    }
+    for i in 0..a.len() {
+        if a[i] {
+            a[i] = false;
+            if rank_agrees::<T>(&a, index) {
+                a[i] = true;
+            }
+        }
+    }
+    let ones: Vec<usize> = (0..a.len()).filter(|&i| a[i]).collect();
+    let expected = ones.iter().filter(|&&i| i < index).count();
+    panic!(
+        "rank({index}) disagrees for pattern `{pattern}` with n = {n}; minimized reproducer:\n\
+         \n\
+         let mut a = vec![false; {len}];\n\
+         for i in {ones:?} {{\n    a[i] = true;\n}}\n\
+         let bvec: {ty} = a.iter().copied().collect();\n\
+         assert_eq!(bvec.rank({index}), {expected});\n",
+        len = a.len(),
+        ty = std::any::type_name::<T>(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Off by one whenever bit 100 is set and the query is past 300.
+    struct Broken(Vec<bool>);
+
+    impl FromIterator<bool> for Broken {
+        fn from_iter<T: IntoIterator<Item = bool>>(iter: T) -> Self {
+            Self(iter.into_iter().collect())
+        }
+    }
+
+    impl RankDataStructure for Broken {
+        fn rank(&self, index: usize) -> usize {
+            let ans = self.0[..index].iter().filter(|&&b| b).count();
+            ans + usize::from(index > 300 && self.0[100])
+        }
+
+        fn space_breakdown(&self) -> crate::SpaceBreakdown {
+            crate::SpaceBreakdown {
+                words: self.0.len(),
+                block: 0,
+                sblock: 0,
+            }
+        }
+    }
+
+    #[test]
+    fn test_exhaustive_minimizes() {
+        let message = std::panic::catch_unwind(test_rank_exhaustive::<Broken>)
+            .unwrap_err()
+            .downcast::<std::string::String>()
+            .unwrap();
+        assert!(message.contains("pattern `ones` with n = 301"), "{message}");
+        assert!(
+            message.contains("let mut a = vec![false; 301];"),
+            "{message}"
+        );
+        assert!(message.contains("for i in [100] {"), "{message}");
+        assert!(
+            message.contains("assert_eq!(bvec.rank(301), 1);"),
+            "{message}"
+        );
+    }
+}
@@ -0,0 +1,13 @@
+use std::process::Command;
+
+/// The core layouts must build as `#![no_std]` + `alloc` when the `std` feature is off.
+#[test]
+fn build_without_std() {
+    let status = Command::new(env!("CARGO"))
+        .args(["build", "--lib", "--no-default-features"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .env("CARGO_TARGET_DIR", env!("CARGO_TARGET_TMPDIR"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+}